@@ -0,0 +1,207 @@
+use std::collections::BTreeSet;
+
+use wasmparser::{BinaryReaderError, ExternalKind, Parser, Payload, Validator, WasmFeatures};
+
+/// Prefix of the marker exports a contract uses to signal that it needs a chain capability,
+/// e.g. `requires_iterator` or `requires_cosmwasm_2_0`.
+const REQUIRES_PREFIX: &str = "requires_";
+
+/// Entry points the VM calls by name
+const ENTRY_POINTS: &[&str] = &[
+    "instantiate",
+    "execute",
+    "query",
+    "migrate",
+    "sudo",
+    "reply",
+];
+
+/// Prefixes of the IBC entry points, e.g. `ibc_channel_open` or `ibc2_packet_receive`
+const IBC_ENTRY_POINT_PREFIXES: &[&str] = &["ibc_", "ibc2_"];
+
+/// The IBC entry points the VM expects as a complete set once a contract exports any of them
+const IBC_CHANNEL_ENTRY_POINTS: &[&str] = &[
+    "ibc_channel_open",
+    "ibc_channel_connect",
+    "ibc_channel_close",
+    "ibc_packet_receive",
+    "ibc_packet_ack",
+    "ibc_packet_timeout",
+];
+
+/// Facts extracted from an optimized artifact after the build.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Analysis {
+    /// Capabilities the chain must support to accept the artifact, without the `requires_` prefix.
+    pub required_capabilities: BTreeSet<String>,
+    /// Exported CosmWasm entry points
+    pub entry_points: BTreeSet<String>,
+}
+
+/// Inspects the given Wasm bytecode.
+pub fn analyze(wasm: &[u8]) -> Result<Analysis, BinaryReaderError> {
+    let exports = exported_functions(wasm)?;
+
+    let required_capabilities = exports
+        .iter()
+        .filter_map(|name| name.strip_prefix(REQUIRES_PREFIX))
+        .map(|capability| capability.to_string())
+        .collect();
+
+    let entry_points = exports
+        .iter()
+        .filter(|name| is_entry_point(name))
+        .cloned()
+        .collect();
+
+    Ok(Analysis {
+        required_capabilities,
+        entry_points,
+    })
+}
+
+impl Analysis {
+    /// Checks that the artifact exports either none or all of the IBC channel and packet entry points.
+    pub fn check_ibc_entry_points(&self) -> Result<(), String> {
+        let (present, missing): (Vec<&str>, Vec<&str>) = IBC_CHANNEL_ENTRY_POINTS
+            .iter()
+            .partition(|entry_point| self.entry_points.contains(**entry_point));
+        if present.is_empty() || missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "incomplete set of IBC entry points: exports {:?} but not {:?}",
+                present, missing
+            ))
+        }
+    }
+}
+
+fn is_entry_point(export: &str) -> bool {
+    ENTRY_POINTS.contains(&export)
+        || IBC_ENTRY_POINT_PREFIXES
+            .iter()
+            .any(|prefix| export.starts_with(prefix))
+}
+
+/// Validates the Wasm bytecode, only allowing the given proposals.
+pub fn validate(wasm: &[u8], features: WasmFeatures) -> Result<(), BinaryReaderError> {
+    Validator::new_with_features(features).validate_all(wasm)?;
+    Ok(())
+}
+
+/// Returns the names of all functions exported by the module.
+fn exported_functions(wasm: &[u8]) -> Result<BTreeSet<String>, BinaryReaderError> {
+    let mut exports = BTreeSet::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ExportSection(reader) = payload? {
+            for export in reader {
+                let export = export?;
+                if export.kind == ExternalKind::Func {
+                    exports.insert(export.name.to_string());
+                }
+            }
+        }
+    }
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_works() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (global (export "requires_global") i32 (i32.const 0))
+                (func (export "instantiate"))
+                (func (export "query"))
+                (func (export "ibc_channel_open"))
+                (func (export "allocate"))
+                (func (export "requires_iterator"))
+                (func (export "requires_cosmwasm_2_0"))
+            )
+            "#,
+        )
+        .unwrap();
+
+        let analysis = analyze(&wasm).unwrap();
+        assert_eq!(
+            analysis.required_capabilities,
+            BTreeSet::from(["cosmwasm_2_0".to_string(), "iterator".to_string()])
+        );
+        assert_eq!(
+            analysis.entry_points,
+            BTreeSet::from([
+                "ibc_channel_open".to_string(),
+                "instantiate".to_string(),
+                "query".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn analyze_works_for_library() {
+        let wasm = wat::parse_str(r#"(module (func (export "allocate")))"#).unwrap();
+
+        let analysis = analyze(&wasm).unwrap();
+        assert_eq!(analysis, Analysis::default());
+    }
+
+    #[test]
+    fn check_ibc_entry_points_works() {
+        let with_entry_points = |entry_points: &[&str]| Analysis {
+            entry_points: entry_points.iter().map(|e| e.to_string()).collect(),
+            ..Analysis::default()
+        };
+
+        // no IBC
+        with_entry_points(&["instantiate", "execute"])
+            .check_ibc_entry_points()
+            .unwrap();
+
+        // complete
+        let mut entry_points = vec!["instantiate"];
+        entry_points.extend(IBC_CHANNEL_ENTRY_POINTS);
+        with_entry_points(&entry_points)
+            .check_ibc_entry_points()
+            .unwrap();
+
+        // incomplete
+        let err = with_entry_points(&["instantiate", "ibc_channel_open", "ibc_packet_receive"])
+            .check_ibc_entry_points()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "incomplete set of IBC entry points: exports [\"ibc_channel_open\", \"ibc_packet_receive\"] \
+            but not [\"ibc_channel_connect\", \"ibc_channel_close\", \"ibc_packet_ack\", \"ibc_packet_timeout\"]"
+        );
+    }
+
+    #[test]
+    fn validate_works() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "extend") (param i32) (result i32)
+                    local.get 0
+                    i32.extend8_s
+                )
+            )
+            "#,
+        )
+        .unwrap();
+
+        validate(&wasm, WasmFeatures::WASM1 | WasmFeatures::SIGN_EXTENSION).unwrap();
+        let err = validate(&wasm, WasmFeatures::WASM1).unwrap_err();
+        assert!(err.message().contains("sign extension"), "{}", err);
+    }
+
+    #[test]
+    fn analyze_fails_for_invalid_wasm() {
+        analyze(b"\0asm\x01").unwrap_err();
+    }
+}
@@ -1,5 +1,106 @@
-use bob_the_builder::build;
+use std::{path::PathBuf, process::exit};
+
+use bob_the_builder::{
+    build, bundle, fetch, verify, Layout, MessageFormat, Options, ToolchainPolicy,
+};
+
+const USAGE: &str = "Usage: bob [build] [--artifacts-dir=DIR] [--check-determinism] [--offline]
+                 [--toolchain=warn|fail|use] [--no-cache] [--schema]
+                 [--name-template=TEMPLATE] [--layout=flat|per-package] [--gzip]
+                 [--intermediate-checksums] [--junit=FILE] [--summary=FILE]
+                 [--previous-manifest=FILE] [--message-format=human|json]
+       bob verify [--artifacts-dir=DIR] [--check-determinism] [--offline]
+                  [--toolchain=warn|fail|use] [--name-template=TEMPLATE]
+                  [--layout=flat|per-package] [--gzip] [--reference=FILE]
+                  [--message-format=human|json]
+       bob bundle [--artifacts-dir=DIR] [--output=FILE]
+       bob fetch";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    exit(1);
+}
 
 fn main() {
-    build();
+    let mut args = std::env::args().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("build") | Some("verify") | Some("bundle") | Some("fetch") => args.next().unwrap(),
+        _ => "build".to_string(),
+    };
+
+    let mut options = Options::default();
+    let mut reference = None;
+    let mut output = None;
+    for arg in args {
+        let builds = command == "build" || command == "verify";
+        if let Some(dir) = arg
+            .strip_prefix("--artifacts-dir=")
+            .filter(|_| command != "fetch")
+        {
+            options.artifacts_dir = PathBuf::from(dir);
+        } else if arg == "--check-determinism" && builds {
+            options.check_determinism = true;
+        } else if arg == "--offline" && builds {
+            options.offline = true;
+        } else if arg == "--no-cache" && command == "build" {
+            options.no_cache = true;
+        } else if arg == "--gzip" && builds {
+            options.gzip = true;
+        } else if arg == "--intermediate-checksums" && command == "build" {
+            options.intermediate_checksums = true;
+        } else if let Some(file) = arg.strip_prefix("--junit=").filter(|_| command == "build") {
+            options.junit = Some(PathBuf::from(file));
+        } else if let Some(file) = arg
+            .strip_prefix("--summary=")
+            .filter(|_| command == "build")
+        {
+            options.summary = Some(PathBuf::from(file));
+        } else if let Some(file) = arg
+            .strip_prefix("--previous-manifest=")
+            .filter(|_| command == "build")
+        {
+            options.previous_manifest = Some(PathBuf::from(file));
+        } else if let Some(format) = arg.strip_prefix("--message-format=").filter(|_| builds) {
+            options.message_format = format
+                .parse::<MessageFormat>()
+                .unwrap_or_else(|err| usage_error(&err));
+        } else if arg == "--schema" && command == "build" {
+            options.schema = true;
+        } else if let Some(template) = arg.strip_prefix("--name-template=").filter(|_| builds) {
+            options.name_template = Some(template.to_string());
+        } else if let Some(layout) = arg.strip_prefix("--layout=").filter(|_| builds) {
+            options.layout = layout
+                .parse::<Layout>()
+                .unwrap_or_else(|err| usage_error(&err));
+        } else if let Some(policy) = arg.strip_prefix("--toolchain=").filter(|_| builds) {
+            options.toolchain_policy = policy
+                .parse::<ToolchainPolicy>()
+                .unwrap_or_else(|err| usage_error(&err));
+        } else if let Some(file) = arg
+            .strip_prefix("--reference=")
+            .filter(|_| command == "verify")
+        {
+            reference = Some(PathBuf::from(file));
+        } else if let Some(file) = arg
+            .strip_prefix("--output=")
+            .filter(|_| command == "bundle")
+        {
+            output = Some(PathBuf::from(file));
+        } else {
+            usage_error(&format!("Unknown argument: {}", arg));
+        }
+    }
+
+    match command.as_str() {
+        "verify" => {
+            if !verify(&options, reference.as_deref()) {
+                exit(1);
+            }
+        }
+        "bundle" => {
+            bundle(&options, output.as_deref());
+        }
+        "fetch" => fetch(),
+        _ => build(&options),
+    }
 }
@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tar::{Builder, EntryType, Header};
+
+use crate::{
+    checksum::sha256,
+    compress::gzip,
+    report::{without_timings, MANIFEST_FILE},
+};
+
+/// Name of the manifest at the top of the bundle, listing all other entries
+pub const BUNDLE_MANIFEST_FILE: &str = "bundle.json";
+
+/// Modification time of all entries, the Unix epoch
+const MTIME: u64 = 0;
+
+/// Permissions of all entries
+const MODE: u32 = 0o644;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct BundleManifest {
+    files: Vec<BundleFile>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct BundleFile {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Returns the absolute form of the file *path* without `.` and `..` components. Unlike
+/// `fs::canonicalize`, this works for files that do not exist yet, like the first bundle.
+fn canonical_file(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Returns the paths of all files in *dir* relative to *dir*, sorted and with `/` as separator.
+/// Skips the file *exclude*, however it is spelled.
+fn list_files(dir: &Path, exclude: &Path) -> Vec<String> {
+    let exclude = canonical_file(exclude);
+    let mut files = vec![];
+    let mut queue = vec![dir.to_path_buf()];
+    while let Some(current) = queue.pop() {
+        for entry in fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                queue.push(path);
+            } else if canonical_file(&path) != exclude {
+                let relative = path.strip_prefix(dir).unwrap();
+                let components = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>();
+                files.push(components.join("/"));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Appends a regular file entry whose header only depends on *path* and *data*.
+fn append(archive: &mut Builder<Vec<u8>>, path: &str, data: &[u8]) {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(MODE);
+    header.set_mtime(MTIME);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("").unwrap();
+    header.set_groupname("").unwrap();
+    archive.append_data(&mut header, path, data).unwrap();
+}
+
+/// Packs all files in *artifacts_dir* into a gzip compressed tarball that only depends on
+/// their paths and contents: entries are sorted by path and have a fixed modification time,
+/// owner and permissions. The first entry is `bundle.json`, which lists the size and SHA-256
+/// hash of every other entry. The file *exclude* (usually the bundle itself) is left out, and so
+/// are the timings in `manifest.json`, which differ between runs.
+pub fn bundle(artifacts_dir: &Path, exclude: &Path) -> Vec<u8> {
+    let files = list_files(artifacts_dir, exclude)
+        .into_iter()
+        .map(|path| {
+            let data = fs::read(artifacts_dir.join(&path)).unwrap();
+            let data = match path.as_str() {
+                MANIFEST_FILE => without_timings(&data).map_or(data, String::into_bytes),
+                _ => data,
+            };
+            (path, data)
+        })
+        .collect::<Vec<_>>();
+    let manifest = BundleManifest {
+        files: files
+            .iter()
+            .map(|(path, data)| BundleFile {
+                path: path.clone(),
+                size: data.len() as u64,
+                sha256: sha256(data),
+            })
+            .collect(),
+    };
+
+    let mut archive = Builder::new(vec![]);
+    let manifest = serde_json::to_string_pretty(&manifest).unwrap() + "\n";
+    append(&mut archive, BUNDLE_MANIFEST_FILE, manifest.as_bytes());
+    for (path, data) in &files {
+        append(&mut archive, path, data);
+    }
+    gzip(&archive.into_inner().unwrap())
+}
+
+/// Returns the default bundle path, `<artifacts_dir>.tar.gz` next to the artifacts directory.
+pub fn default_output(artifacts_dir: &Path) -> PathBuf {
+    // Without a trailing separator, which would put the bundle into the directory
+    let mut output = artifacts_dir.components().as_path().as_os_str().to_owned();
+    output.push(".tar.gz");
+    PathBuf::from(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Read, time::Duration};
+
+    use flate2::read::GzDecoder;
+    use tar::Archive;
+
+    use super::*;
+
+    use crate::{
+        cache::Cache,
+        report::{test_artifact, write_manifest, write_metadata, Artifact},
+        timing::{Phase, Timings},
+    };
+
+    #[test]
+    fn bundle_works() {
+        let artifacts_dir = env::temp_dir().join(format!("bob-bundle-{}", std::process::id()));
+        fs::create_dir_all(artifacts_dir.join("my_contract/schema")).unwrap();
+        fs::write(artifacts_dir.join("my_contract.wasm"), b"wasm").unwrap();
+        fs::write(artifacts_dir.join("checksums.txt"), b"").unwrap();
+        let mut timings = Timings::default();
+        timings.record(None, None, Phase::Checksums, Duration::from_millis(3));
+        write_manifest(&artifacts_dir, &[], timings.report());
+        fs::write(
+            artifacts_dir.join("my_contract/schema/my_contract.json"),
+            b"{}",
+        )
+        .unwrap();
+        let output = artifacts_dir.join("bundle.tar.gz");
+        fs::write(&output, b"previous bundle").unwrap();
+
+        let bundled = bundle(&artifacts_dir, &output);
+        assert_eq!(bundled, bundle(&artifacts_dir, &output));
+        // Other spellings of the output are excluded as well
+        let dotted = artifacts_dir.join(".").join("bundle.tar.gz");
+        assert_eq!(bundled, bundle(&artifacts_dir, &dotted));
+        let parent = artifacts_dir.join("my_contract/../bundle.tar.gz");
+        assert_eq!(bundled, bundle(&artifacts_dir, &parent));
+
+        let mut tar = vec![];
+        GzDecoder::new(bundled.as_slice())
+            .read_to_end(&mut tar)
+            .unwrap();
+        let mut archive = Archive::new(tar.as_slice());
+        let mut paths = vec![];
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let header = entry.header();
+            assert_eq!(header.mtime().unwrap(), MTIME);
+            assert_eq!(header.mode().unwrap(), MODE);
+            assert_eq!(header.uid().unwrap(), 0);
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            if path == MANIFEST_FILE {
+                let mut manifest = String::new();
+                entry.read_to_string(&mut manifest).unwrap();
+                assert_eq!(manifest, "{\n  \"artifacts\": []\n}\n");
+            }
+            paths.push(path);
+        }
+        assert_eq!(
+            paths,
+            vec![
+                "bundle.json",
+                "checksums.txt",
+                "manifest.json",
+                "my_contract.wasm",
+                "my_contract/schema/my_contract.json",
+            ]
+        );
+
+        fs::remove_dir_all(&artifacts_dir).unwrap();
+    }
+
+    #[test]
+    fn bundle_is_the_same_for_cold_and_warm_runs() {
+        let root = env::temp_dir().join(format!("bob-bundle-warm-{}", std::process::id()));
+        let cache = Cache::new(root.join("cache"));
+        let artifact = Artifact {
+            sha256: sha256(b"wasm"),
+            ..test_artifact("my_contract", "")
+        };
+
+        // Cold run: the artifact is built and stored in the cache
+        let cold = root.join("cold");
+        fs::create_dir_all(&cold).unwrap();
+        fs::write(cold.join("my_contract.wasm"), b"wasm").unwrap();
+        cache.store("key", &artifact, &cold);
+        write_metadata(&cold, &artifact);
+        write_manifest(&cold, &[artifact], Timings::default().report());
+
+        // Warm run: the artifact comes from the cache
+        let warm = root.join("warm");
+        let cached = cache.load("key", &warm).unwrap();
+        assert!(cached.cache_hit);
+        write_metadata(&warm, &cached);
+        let mut timings = Timings::default();
+        timings.record(None, None, Phase::Compile, Duration::from_secs(1));
+        write_manifest(&warm, &[cached], timings.report());
+
+        assert_eq!(
+            bundle(&cold, &root.join("cold.tar.gz")),
+            bundle(&warm, &root.join("warm.tar.gz"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn canonical_file_works() {
+        let current = fs::canonicalize(".").unwrap();
+        assert_eq!(
+            canonical_file(Path::new("./src/../missing.tar.gz")),
+            current.join("missing.tar.gz")
+        );
+        assert_eq!(
+            canonical_file(Path::new("missing.tar.gz")),
+            current.join("missing.tar.gz")
+        );
+    }
+
+    #[test]
+    fn default_output_works() {
+        assert_eq!(
+            default_output(Path::new("artifacts")),
+            PathBuf::from("artifacts.tar.gz")
+        );
+        assert_eq!(
+            default_output(Path::new("out/artifacts/")),
+            PathBuf::from("out/artifacts.tar.gz")
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    cargo_command, cargo_toml::package::Build, checksum::sha256, optimize, report::Artifact,
+    toolchain::Toolchain, Options,
+};
+
+/// Where the optimized artifacts of previous runs are kept, one directory per input hash
+pub const CACHE_DIR: &str = "/target/bob-cache";
+
+/// Name of the file holding the report of a cached artifact
+const CACHED_ARTIFACT_FILE: &str = "artifact.json";
+
+#[derive(Deserialize, Debug)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetadataPackage {
+    id: String,
+    name: String,
+    version: String,
+    /// `None` for path dependencies
+    source: Option<String>,
+    manifest_path: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+struct Resolve {
+    root: Option<String>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Node {
+    id: String,
+    dependencies: Vec<String>,
+}
+
+/// Returns the package the metadata was created for and everything it depends on.
+fn reachable_packages(metadata: &Metadata) -> Vec<&MetadataPackage> {
+    let Some(Resolve {
+        root: Some(root),
+        nodes,
+    }) = &metadata.resolve
+    else {
+        return metadata.packages.iter().collect();
+    };
+    let dependencies = nodes
+        .iter()
+        .map(|node| (&node.id, &node.dependencies))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut reachable = BTreeSet::new();
+    let mut queue = vec![root];
+    while let Some(id) = queue.pop() {
+        if reachable.insert(id) {
+            queue.extend(
+                dependencies
+                    .get(id)
+                    .into_iter()
+                    .flat_map(|deps| deps.iter()),
+            );
+        }
+    }
+    metadata
+        .packages
+        .iter()
+        .filter(|package| reachable.contains(&package.id))
+        .collect()
+}
+
+/// Hashes all files in *dir* by their path relative to *dir*, skipping hidden entries,
+/// `target` directories and the directories in *exclude*.
+fn hash_sources(dir: &Path, exclude: &[PathBuf]) -> BTreeMap<String, String> {
+    let mut hashes = BTreeMap::new();
+    let mut queue = vec![dir.to_path_buf()];
+    while let Some(current) = queue.pop() {
+        for entry in fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if name.starts_with('.') || name == "target" {
+                continue;
+            }
+            if path.is_dir() {
+                if !exclude.contains(&fs::canonicalize(&path).unwrap()) {
+                    queue.push(path);
+                }
+            } else {
+                let relative = path
+                    .strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                hashes.insert(relative, sha256(&fs::read(&path).unwrap()));
+            }
+        }
+    }
+    hashes
+}
+
+/// The inputs shared by all builds of a package
+#[derive(Debug)]
+pub struct PackageInputs {
+    /// Hashes of the source files of the package and its path dependencies by package directory
+    sources: BTreeMap<String, BTreeMap<String, String>>,
+    /// `<name> <version> <source>` of all other dependencies as resolved from `Cargo.lock`
+    dependencies: BTreeSet<String>,
+    /// Hash of the root `Cargo.toml`, which contains profiles and workspace dependencies
+    root_manifest: String,
+    toolchain: Toolchain,
+    wasm_opt_version: String,
+}
+
+impl PackageInputs {
+    /// Collects the inputs of the package in *dir* using `cargo metadata`.
+    pub fn collect(dir: &Path, options: &Options, toolchain: &Toolchain) -> Self {
+        let output = cargo_command(options, toolchain)
+            .args([
+                "metadata",
+                "--locked",
+                "--format-version=1",
+                "--filter-platform=wasm32-unknown-unknown",
+            ])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "cargo metadata failed");
+        let metadata: Metadata = serde_json::from_slice(&output.stdout).unwrap();
+
+        let exclude = fs::canonicalize(&options.artifacts_dir)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut sources = BTreeMap::new();
+        let mut dependencies = BTreeSet::new();
+        for package in reachable_packages(&metadata) {
+            match &package.source {
+                Some(source) => {
+                    dependencies.insert(format!("{} {} {}", package.name, package.version, source));
+                }
+                None => {
+                    let package_dir = package.manifest_path.parent().unwrap();
+                    sources.insert(
+                        package_dir.display().to_string(),
+                        hash_sources(package_dir, &exclude),
+                    );
+                }
+            }
+        }
+
+        PackageInputs {
+            sources,
+            dependencies,
+            root_manifest: sha256(&fs::read("Cargo.toml").unwrap()),
+            toolchain: toolchain.clone(),
+            wasm_opt_version: optimize::version(),
+        }
+    }
+
+    /// Returns the hash of everything that goes into the artifact *file* of *build*.
+    pub fn key(
+        &self,
+        build: &Build,
+        file: &str,
+        rustflags: &[String],
+        wasm_opt_flags: &[String],
+    ) -> String {
+        let inputs = json!({
+            "bob": env!("CARGO_PKG_VERSION"),
+            "sources": self.sources,
+            "dependencies": self.dependencies,
+            "root_manifest": self.root_manifest,
+            "toolchain": [&self.toolchain.name, &self.toolchain.rustc_version],
+            "build": build.name,
+            "file": file,
+            "features": build.settings.features,
+            "default_features": build.settings.default_features,
+            "cosmwasm_target": build.settings.cosmwasm_target,
+            "rustflags": rustflags,
+            "wasm_opt": self.wasm_opt_version,
+            "wasm_opt_flags": wasm_opt_flags,
+        });
+        sha256(inputs.to_string().as_bytes())
+    }
+}
+
+/// Optimized artifacts of previous runs by input hash
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// Copies the artifact cached under *key* to *artifacts_dir* if there is one.
+    pub fn load(&self, key: &str, artifacts_dir: &Path) -> Option<Artifact> {
+        let entry = self.dir.join(key);
+        let json = fs::read(entry.join(CACHED_ARTIFACT_FILE)).ok()?;
+        let mut artifact: Artifact = serde_json::from_slice(&json).ok()?;
+        let wasm = fs::read(entry.join(&artifact.file)).ok()?;
+        if sha256(&wasm) != artifact.sha256 {
+            return None;
+        }
+        let output = artifacts_dir.join(&artifact.file);
+        fs::create_dir_all(output.parent().unwrap()).unwrap();
+        fs::write(output, wasm).unwrap();
+        artifact.cache_hit = true;
+        Some(artifact)
+    }
+
+    /// Keeps a copy of the *artifact* from *artifacts_dir* under *key*.
+    pub fn store(&self, key: &str, artifact: &Artifact, artifacts_dir: &Path) {
+        let entry = self.dir.join(key);
+        fs::create_dir_all(entry.join(&artifact.file).parent().unwrap()).unwrap();
+        fs::copy(
+            artifacts_dir.join(&artifact.file),
+            entry.join(&artifact.file),
+        )
+        .unwrap();
+        let json = serde_json::to_string_pretty(artifact).unwrap();
+        fs::write(entry.join(CACHED_ARTIFACT_FILE), json + "\n").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    use crate::report::test_artifact;
+
+    #[test]
+    fn reachable_packages_works() {
+        let metadata: Metadata = serde_json::from_str(
+            r#"{
+                "packages": [
+                    { "id": "a", "name": "a", "version": "1.0.0", "source": null, "manifest_path": "/code/contracts/a/Cargo.toml" },
+                    { "id": "b", "name": "b", "version": "1.0.0", "source": null, "manifest_path": "/code/contracts/b/Cargo.toml" },
+                    { "id": "serde", "name": "serde", "version": "1.0.0", "source": "registry+https://github.com/rust-lang/crates.io-index", "manifest_path": "/cargo/serde/Cargo.toml" },
+                    { "id": "rand", "name": "rand", "version": "0.8.0", "source": "registry+https://github.com/rust-lang/crates.io-index", "manifest_path": "/cargo/rand/Cargo.toml" }
+                ],
+                "resolve": {
+                    "root": "a",
+                    "nodes": [
+                        { "id": "a", "dependencies": ["serde"] },
+                        { "id": "b", "dependencies": ["rand", "serde"] },
+                        { "id": "serde", "dependencies": [] },
+                        { "id": "rand", "dependencies": [] }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+        let names = reachable_packages(&metadata)
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "serde"]);
+    }
+
+    #[test]
+    fn hash_sources_works() {
+        let dir = env::temp_dir().join(format!("bob-cache-sources-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::create_dir_all(dir.join("artifacts")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        fs::write(dir.join("target/out.wasm"), "").unwrap();
+        fs::write(dir.join("artifacts/out.wasm"), "").unwrap();
+
+        let hashes = hash_sources(&dir, &[fs::canonicalize(dir.join("artifacts")).unwrap()]);
+        assert_eq!(
+            hashes.keys().collect::<Vec<_>>(),
+            vec!["Cargo.toml", "src/lib.rs"]
+        );
+        assert_eq!(hashes["src/lib.rs"], sha256(b""));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_and_store_work() {
+        let root = env::temp_dir().join(format!("bob-cache-{}", std::process::id()));
+        let artifacts_dir = root.join("artifacts");
+        fs::create_dir_all(&artifacts_dir).unwrap();
+        let cache = Cache::new(root.join("cache"));
+
+        let artifact = Artifact {
+            sha256: sha256(b"wasm"),
+            ..test_artifact("my_contract", "")
+        };
+
+        fs::write(artifacts_dir.join("my_contract.wasm"), b"wasm").unwrap();
+
+        assert_eq!(cache.load("key", &artifacts_dir), None);
+        cache.store("key", &artifact, &artifacts_dir);
+        fs::remove_file(artifacts_dir.join("my_contract.wasm")).unwrap();
+
+        let loaded = cache.load("key", &artifacts_dir).unwrap();
+        assert!(loaded.cache_hit);
+        assert_eq!(
+            loaded,
+            Artifact {
+                cache_hit: true,
+                ..artifact
+            }
+        );
+        assert_eq!(
+            fs::read(artifacts_dir.join("my_contract.wasm")).unwrap(),
+            b"wasm"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -9,6 +9,34 @@ pub mod workspace {
     #[derive(Deserialize, Debug)]
     pub struct Workspace {
         pub members: Option<Vec<String>>,
+        pub package: Option<WorkspacePackage>,
+        pub metadata: Option<WorkspaceMetadata>,
+    }
+
+    /// Values members can inherit via `<key>.workspace = true`
+    #[derive(Deserialize, Debug)]
+    pub struct WorkspacePackage {
+        pub version: Option<String>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct WorkspaceMetadata {
+        pub optimizer: Option<WorkspaceOptimizer>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(rename_all = "kebab-case")]
+    pub struct WorkspaceOptimizer {
+        /// The artifact name template used by all packages that do not set their own.
+        pub name_template: Option<String>,
+    }
+
+    /// The settings of the root `Cargo.toml` that apply to all packages
+    #[derive(Debug, Default, PartialEq)]
+    pub struct WorkspaceSettings {
+        /// `workspace.package.version`
+        pub version: Option<String>,
+        pub name_template: Option<String>,
     }
 
     #[derive(Debug, PartialEq)]
@@ -40,10 +68,52 @@ pub mod workspace {
         }
     }
 
+    /// Gets the settings shared by all packages from the root `Cargo.toml`.
+    pub fn parse_settings(file: &str) -> Result<WorkspaceSettings, toml::de::Error> {
+        let parsed: CargoToml = toml::from_str(file)?;
+        let Some(workspace) = parsed.workspace else {
+            return Ok(WorkspaceSettings::default());
+        };
+        Ok(WorkspaceSettings {
+            version: workspace.package.and_then(|package| package.version),
+            name_template: workspace
+                .metadata
+                .and_then(|metadata| metadata.optimizer)
+                .and_then(|optimizer| optimizer.name_template),
+        })
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
 
+        #[test]
+        fn parse_settings_works() {
+            let settings = parse_settings(
+                r#"
+            [workspace]
+            members = ["contracts/*"]
+
+            [workspace.package]
+            version = "1.2.0"
+
+            [workspace.metadata.optimizer]
+            name-template = "{package}-{version}-{build}"
+            "#,
+            )
+            .unwrap();
+            assert_eq!(
+                settings,
+                WorkspaceSettings {
+                    version: Some("1.2.0".to_string()),
+                    name_template: Some("{package}-{version}-{build}".to_string()),
+                }
+            );
+
+            let settings = parse_settings("[package]\nname = \"my-contract\"\n").unwrap();
+            assert_eq!(settings, WorkspaceSettings::default());
+        }
+
         #[test]
         fn is_workspace_works() {
             let is = is_workspace(
@@ -96,7 +166,7 @@ pub mod workspace {
 pub mod package {
     use std::{collections::BTreeSet, hash::Hash};
 
-    use serde::Deserialize;
+    use serde::{de::IgnoredAny, Deserialize, Serialize};
 
     use crate::pkg_build::ParsedPackage;
 
@@ -111,9 +181,19 @@ pub mod package {
     #[derive(Deserialize, Debug)]
     pub struct Package {
         name: String,
+        version: Option<PackageVersion>,
         metadata: Option<OptimizerMetadata>,
     }
 
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum PackageVersion {
+        /// Only the form is checked, the version itself does not matter here
+        Version(#[allow(dead_code)] String),
+        /// `version.workspace = true`
+        Inherited(IgnoredAny),
+    }
+
     #[derive(Deserialize, Debug)]
     pub struct OptimizerMetadata {
         optimizer: Option<Optimizer>,
@@ -127,6 +207,10 @@ pub mod package {
         standard_build: Option<bool>,
         /// A collection of named build configurations.
         builds: Option<Vec<Build>>,
+        /// The CosmWasm version all builds of this package target unless a build overrides it.
+        cosmwasm_target: Option<CosmWasmTarget>,
+        /// The template for the artifact file names, e.g. `{package}-{version}-{build}`.
+        name_template: Option<String>,
     }
 
     /// A build entry that specifies the build of a contract with optional features.
@@ -147,6 +231,21 @@ pub mod package {
         /// Indicates if default features should be enabled for this build.
         /// Default to true.
         pub default_features: Option<bool>,
+        /// The CosmWasm version of the chains this build is uploaded to.
+        /// Defaults to the package setting or 3.x.
+        pub cosmwasm_target: Option<CosmWasmTarget>,
+    }
+
+    /// A range of CosmWasm versions with the same set of supported Wasm proposals.
+    #[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq, Eq, Hash)]
+    pub enum CosmWasmTarget {
+        #[serde(rename = "1.x")]
+        V1,
+        #[serde(rename = "2.x")]
+        V2,
+        #[default]
+        #[serde(rename = "3.x")]
+        V3,
     }
 
     /// Get all the builds and wasm name from the `Cargo.toml` file.
@@ -158,10 +257,18 @@ pub mod package {
             .and_then(|metadata| metadata.optimizer)
             .unwrap_or_default();
 
+        let version = match package.version {
+            Some(PackageVersion::Version(version)) => Some(version),
+            Some(PackageVersion::Inherited(_)) | None => None,
+        };
+
         Ok(ParsedPackage {
             name: package.name.replace("-", "_"),
+            version,
             standard_build: optimizer.standard_build.unwrap_or(true),
             builds: optimizer.builds.unwrap_or_default(),
+            cosmwasm_target: optimizer.cosmwasm_target,
+            name_template: optimizer.name_template,
         })
     }
 
@@ -182,8 +289,11 @@ pub mod package {
                 parsed,
                 ParsedPackage {
                     name: "my_contract".to_string(),
+                    version: None,
                     standard_build: true,
-                    builds: vec![]
+                    builds: vec![],
+                    cosmwasm_target: None,
+                    name_template: None,
                 }
             );
         }
@@ -193,13 +303,16 @@ pub mod package {
             let toml = r#"
             [package]
             name = "my-contract"
+            version = "0.3.1"
 
             [package.metadata.optimizer]
             standard-build = false
+            cosmwasm-target = "2.x"
+            name-template = "{package}@{version}-{build}"
             builds = [
                 { name = "optimized", features = ["opt1", "opt2"], default-features = true },
                 { name = "debug", features = ["debug"] },
-                { name = "boring", features = [], default-features = false },
+                { name = "boring", features = [], default-features = false, cosmwasm-target = "1.x" },
             ]
             "#;
 
@@ -209,6 +322,7 @@ pub mod package {
                 parsed,
                 ParsedPackage {
                     name: "my_contract".to_string(),
+                    version: Some("0.3.1".to_string()),
                     standard_build: false,
                     builds: vec![
                         Build {
@@ -218,7 +332,8 @@ pub mod package {
                                     "opt1".to_string(),
                                     "opt2".to_string()
                                 ])),
-                                default_features: Some(true)
+                                default_features: Some(true),
+                                cosmwasm_target: None,
                             }
                         },
                         Build {
@@ -226,18 +341,298 @@ pub mod package {
                             settings: BuildSettings {
                                 features: Some(BTreeSet::from(["debug".to_string()])),
                                 default_features: None,
+                                cosmwasm_target: None,
                             }
                         },
                         Build {
                             name: "boring".to_string(),
                             settings: BuildSettings {
                                 features: Some(BTreeSet::default()),
-                                default_features: Some(false)
+                                default_features: Some(false),
+                                cosmwasm_target: Some(CosmWasmTarget::V1),
                             }
                         }
+                    ],
+                    cosmwasm_target: Some(CosmWasmTarget::V2),
+                    name_template: Some("{package}@{version}-{build}".to_string()),
+                }
+            );
+        }
+
+        #[test]
+        fn parse_toml_works_for_inherited_version() {
+            let toml = r#"
+            [package]
+            name = "my-contract"
+            version.workspace = true
+            "#;
+
+            let parsed = parse_toml(toml).unwrap();
+            assert_eq!(parsed.version, None);
+        }
+    }
+}
+
+pub mod dependencies {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use serde::Deserialize;
+
+    use super::package::Feature;
+
+    #[derive(Deserialize, Debug)]
+    struct PackageCargoToml {
+        package: Package,
+        features: Option<BTreeMap<Feature, Vec<String>>>,
+        dependencies: Option<BTreeMap<String, DependencyEntry>>,
+        /// `[target.'cfg(...)'.dependencies]` by target
+        target: Option<BTreeMap<String, Target>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Target {
+        dependencies: Option<BTreeMap<String, DependencyEntry>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Package {
+        name: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct RootCargoToml {
+        workspace: Option<Workspace>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Workspace {
+        dependencies: Option<BTreeMap<String, DependencyEntry>>,
+    }
+
+    /// A dependency is either a table or just a version string.
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged, expecting = "a version string or a dependency table")]
+    enum DependencyEntry {
+        Detailed(DetailedDependency),
+        /// Only the form is checked, the version itself does not matter here
+        Version(#[allow(dead_code)] String),
+    }
+
+    #[derive(Deserialize, Debug, Default)]
+    struct DetailedDependency {
+        /// The real package name if the dependency is renamed
+        package: Option<String>,
+        features: Option<BTreeSet<Feature>>,
+        /// Set for `foo = { workspace = true }`
+        workspace: Option<bool>,
+        optional: Option<bool>,
+    }
+
+    /// A normal dependency of a package.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub struct Dependency {
+        /// The key of the dependency declaration
+        pub name: String,
+        /// Name of the package depended on, with `-` replaced by `_`
+        pub package: String,
+        /// Features enabled explicitly in this dependency declaration
+        pub features: BTreeSet<Feature>,
+        /// Inherits the declaration from `[workspace.dependencies]`
+        pub workspace: bool,
+        /// Only linked if a feature enables it
+        pub optional: bool,
+    }
+
+    /// The parts of a package's `Cargo.toml` relevant to how it links other packages.
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct PackageDependencies {
+        /// Package name with `-` replaced by `_`
+        pub name: String,
+        /// `[features]` with the features and dependency features each one enables
+        pub features: BTreeMap<Feature, Vec<String>>,
+        /// Entries of `[dependencies]` and of the target specific dependency tables
+        pub dependencies: Vec<Dependency>,
+    }
+
+    impl Dependency {
+        fn from_entry(key: String, entry: DependencyEntry) -> Self {
+            let detailed = match entry {
+                DependencyEntry::Version(_) => DetailedDependency::default(),
+                DependencyEntry::Detailed(detailed) => detailed,
+            };
+            Dependency {
+                package: detailed
+                    .package
+                    .unwrap_or_else(|| key.clone())
+                    .replace('-', "_"),
+                name: key,
+                features: detailed.features.unwrap_or_default(),
+                workspace: detailed.workspace.unwrap_or(false),
+                optional: detailed.optional.unwrap_or(false),
+            }
+        }
+    }
+
+    /// Get the features and normal dependencies, including the target specific ones,
+    /// from a package's `Cargo.toml` file.
+    pub fn parse_toml(file: &str) -> Result<PackageDependencies, toml::de::Error> {
+        let parsed: PackageCargoToml = toml::from_str(file)?;
+        let target_dependencies = parsed
+            .target
+            .unwrap_or_default()
+            .into_values()
+            .flat_map(|target| target.dependencies.unwrap_or_default());
+        Ok(PackageDependencies {
+            name: parsed.package.name.replace('-', "_"),
+            features: parsed.features.unwrap_or_default(),
+            dependencies: parsed
+                .dependencies
+                .unwrap_or_default()
+                .into_iter()
+                .chain(target_dependencies)
+                .map(|(key, entry)| Dependency::from_entry(key, entry))
+                .collect(),
+        })
+    }
+
+    /// Get the `[workspace.dependencies]` from the workspace root's `Cargo.toml` file,
+    /// indexed by the dependency key.
+    pub fn parse_workspace_toml(
+        file: &str,
+    ) -> Result<BTreeMap<String, Dependency>, toml::de::Error> {
+        let parsed: RootCargoToml = toml::from_str(file)?;
+        Ok(parsed
+            .workspace
+            .and_then(|workspace| workspace.dependencies)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, entry)| (key.clone(), Dependency::from_entry(key, entry)))
+            .collect())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_toml_works() {
+            let toml = r#"
+            [package]
+            name = "my-contract"
+
+            [features]
+            library = []
+            debug = ["other-contract/debug"]
+
+            [dependencies]
+            cosmwasm-std = "2.0.0"
+            other-contract = { path = "../other-contract", features = ["library"] }
+            renamed = { package = "third-contract", version = "1.0.0", optional = true }
+            shared = { workspace = true }
+
+            [target.'cfg(target_arch = "wasm32")'.dependencies]
+            wasm-only = { path = "../wasm-only" }
+
+            [dev-dependencies]
+            cw-multi-test = "2.0.0"
+            "#;
+
+            let parsed = parse_toml(toml).unwrap();
+            assert_eq!(
+                parsed,
+                PackageDependencies {
+                    name: "my_contract".to_string(),
+                    features: BTreeMap::from([
+                        (
+                            "debug".to_string(),
+                            vec!["other-contract/debug".to_string()]
+                        ),
+                        ("library".to_string(), vec![]),
+                    ]),
+                    dependencies: vec![
+                        Dependency {
+                            name: "cosmwasm-std".to_string(),
+                            package: "cosmwasm_std".to_string(),
+                            features: BTreeSet::new(),
+                            workspace: false,
+                            optional: false,
+                        },
+                        Dependency {
+                            name: "other-contract".to_string(),
+                            package: "other_contract".to_string(),
+                            features: BTreeSet::from(["library".to_string()]),
+                            workspace: false,
+                            optional: false,
+                        },
+                        Dependency {
+                            name: "renamed".to_string(),
+                            package: "third_contract".to_string(),
+                            features: BTreeSet::new(),
+                            workspace: false,
+                            optional: true,
+                        },
+                        Dependency {
+                            name: "shared".to_string(),
+                            package: "shared".to_string(),
+                            features: BTreeSet::new(),
+                            workspace: true,
+                            optional: false,
+                        },
+                        Dependency {
+                            name: "wasm-only".to_string(),
+                            package: "wasm_only".to_string(),
+                            features: BTreeSet::new(),
+                            workspace: false,
+                            optional: false,
+                        },
                     ]
                 }
             );
         }
+
+        #[test]
+        fn parse_workspace_toml_works() {
+            let toml = r#"
+            [workspace]
+            members = ["contracts/*"]
+
+            [workspace.dependencies]
+            my-contract = { path = "contracts/my-contract", features = ["library"] }
+            "#;
+
+            let parsed = parse_workspace_toml(toml).unwrap();
+            assert_eq!(
+                parsed,
+                BTreeMap::from([(
+                    "my-contract".to_string(),
+                    Dependency {
+                        name: "my-contract".to_string(),
+                        package: "my_contract".to_string(),
+                        features: BTreeSet::from(["library".to_string()]),
+                        workspace: false,
+                        optional: false,
+                    }
+                )])
+            );
+
+            let parsed = parse_workspace_toml("[package]\nname = \"foo\"").unwrap();
+            assert!(parsed.is_empty());
+        }
+
+        #[test]
+        fn malformed_dependencies_are_errors() {
+            let toml = r#"
+            [package]
+            name = "my-contract"
+
+            [dependencies]
+            other-contract = { path = "../other-contract", features = "library" }
+            "#;
+            let err = parse_toml(toml).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("a version string or a dependency table"));
+            parse_toml("[package]\nname = \"a\"\n[dependencies]\nb = 1\n").unwrap_err();
+        }
     }
 }
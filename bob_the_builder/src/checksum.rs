@@ -0,0 +1,39 @@
+use sha2::{Digest, Sha256};
+
+/// Returns the lowercase hex encoded SHA-256 hash of *data*, like `sha256sum` prints it.
+pub fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Formats pairs of file name and SHA-256 hash in the output format of `sha256sum`,
+/// sorted by file name, so `sha256sum -c` can check them.
+pub fn format_checksums<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let mut files = files.into_iter().collect::<Vec<_>>();
+    files.sort();
+    files
+        .into_iter()
+        .map(|(file, sha256)| format!("{}  {}\n", sha256, file))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_checksums_works() {
+        assert_eq!(
+            format_checksums([("two.wasm", "bbbb"), ("one.wasm", "aaaa")]),
+            "aaaa  one.wasm\nbbbb  two.wasm\n"
+        );
+        assert_eq!(format_checksums([]), "");
+    }
+
+    #[test]
+    fn sha256_works() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
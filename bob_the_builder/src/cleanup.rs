@@ -0,0 +1,358 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    compress::GZIP_SUFFIX,
+    naming::Layout,
+    pkg_build::target_wasm_path,
+    report::{Artifact, CHECKSUMS_FILE, INDEX_FILE, MANIFEST_FILE},
+};
+
+/// What we need to know about an artifact of a previous run to remove its outputs
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PreviousArtifact {
+    file: String,
+    package: String,
+    #[serde(default)]
+    build: String,
+    /// Set if a compressed artifact was written
+    #[serde(default)]
+    gzip_sha256: Option<String>,
+    /// Schema files of the package relative to the artifacts directory
+    #[serde(default)]
+    schema: BTreeMap<String, String>,
+}
+
+impl PreviousArtifact {
+    fn of(artifact: &Artifact) -> Self {
+        PreviousArtifact {
+            file: artifact.file.clone(),
+            package: artifact.package.clone(),
+            build: artifact.build.clone(),
+            gzip_sha256: artifact.gzip_sha256.clone(),
+            schema: artifact.schema.clone(),
+        }
+    }
+
+    /// Returns the files this artifact consists of in *artifacts_dir* and in the target directory.
+    fn outputs(&self, artifacts_dir: &Path) -> Vec<PathBuf> {
+        let stem = self.file.strip_suffix(".wasm").unwrap_or(&self.file);
+        let mut outputs = vec![
+            artifacts_dir.join(&self.file),
+            artifacts_dir.join(format!("{}.metadata.json", stem)),
+            target_wasm_path(&self.package, &self.build),
+        ];
+        if self.gzip_sha256.is_some() {
+            outputs.push(artifacts_dir.join(format!("{}{}", self.file, GZIP_SUFFIX)));
+        }
+        outputs.extend(self.schema.keys().map(|file| artifacts_dir.join(file)));
+        outputs
+    }
+}
+
+/// What we need to know about a run to remove its outputs
+#[derive(Deserialize, Debug, Default)]
+pub struct PreviousManifest {
+    artifacts: BTreeSet<PreviousArtifact>,
+    /// The `checksums.txt` files of the packages relative to the artifacts directory as listed
+    /// in `index.json`, `None` in the flat layout
+    #[serde(skip)]
+    package_checksums: Option<BTreeSet<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PreviousIndex {
+    packages: BTreeMap<String, PreviousPackageIndex>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PreviousPackageIndex {
+    checksums: String,
+}
+
+impl PreviousManifest {
+    fn of(artifacts: &[Artifact], layout: Layout) -> Self {
+        PreviousManifest {
+            artifacts: artifacts.iter().map(PreviousArtifact::of).collect(),
+            package_checksums: (layout == Layout::PerPackage).then(|| {
+                artifacts
+                    .iter()
+                    .map(|artifact| format!("{}/{}", artifact.package, CHECKSUMS_FILE))
+                    .collect()
+            }),
+        }
+    }
+
+    /// Returns the files the run wrote to *artifacts_dir* and the target directory, except for
+    /// the ones every run writes.
+    fn outputs(&self, artifacts_dir: &Path) -> BTreeSet<PathBuf> {
+        let mut outputs = self
+            .artifacts
+            .iter()
+            .flat_map(|artifact| artifact.outputs(artifacts_dir))
+            .collect::<BTreeSet<_>>();
+        if let Some(package_checksums) = &self.package_checksums {
+            outputs.extend(
+                package_checksums
+                    .iter()
+                    .map(|file| artifacts_dir.join(file)),
+            );
+            outputs.insert(artifacts_dir.join(INDEX_FILE));
+        }
+        outputs
+    }
+}
+
+/// Returns the manifest and package index of the previous run in *artifacts_dir*.
+/// Returns an empty manifest if there is no readable manifest.
+pub fn previous_manifest(artifacts_dir: &Path) -> PreviousManifest {
+    let mut manifest = fs::read_to_string(artifacts_dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str::<PreviousManifest>(&content).ok())
+        .unwrap_or_default();
+    manifest.package_checksums = fs::read_to_string(artifacts_dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str::<PreviousIndex>(&content).ok())
+        .map(|index| {
+            index
+                .packages
+                .into_values()
+                .map(|package| package.checksums)
+                .collect()
+        });
+    manifest
+}
+
+/// Returns the outputs of the *previous* run that the *current* run does not produce.
+fn stale_outputs(
+    previous: &PreviousManifest,
+    current: &PreviousManifest,
+    artifacts_dir: &Path,
+) -> BTreeSet<PathBuf> {
+    let current = current.outputs(artifacts_dir);
+    previous
+        .outputs(artifacts_dir)
+        .into_iter()
+        .filter(|path| !current.contains(path))
+        .collect()
+}
+
+/// Removes the outputs of the *previous* run that are not part of this run with the given
+/// *artifacts* and *layout*, and the directories in *artifacts_dir* this leaves empty.
+/// Returns the removed files and directories.
+pub fn remove_stale_outputs(
+    previous: &PreviousManifest,
+    artifacts: &[Artifact],
+    layout: Layout,
+    artifacts_dir: &Path,
+) -> Vec<PathBuf> {
+    let current = PreviousManifest::of(artifacts, layout);
+    let mut removed = vec![];
+    for path in stale_outputs(previous, &current, artifacts_dir) {
+        if path.is_file() {
+            fs::remove_file(&path).unwrap();
+            removed.push(path);
+        }
+    }
+    // Deepest first, so that parents are empty once their children are gone
+    let mut dirs = removed
+        .iter()
+        .flat_map(|path| path.ancestors().skip(1))
+        .filter(|dir| dir.starts_with(artifacts_dir) && *dir != artifacts_dir)
+        .map(Path::to_path_buf)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in dirs {
+        let is_empty = fs::read_dir(&dir)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false);
+        if is_empty {
+            fs::remove_dir(&dir).unwrap();
+            removed.push(dir);
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    use crate::{
+        report::{test_artifact, write_manifest, write_package_index},
+        timing::Timings,
+    };
+
+    fn artifact(package: &str, build: &str) -> Artifact {
+        gzip_artifact(package, build, None)
+    }
+
+    fn gzip_artifact(package: &str, build: &str, gzip_sha256: Option<&str>) -> Artifact {
+        Artifact {
+            gzip_sha256: gzip_sha256.map(str::to_string),
+            ..test_artifact(package, build)
+        }
+    }
+
+    fn schema_artifact(package: &str, file: &str) -> Artifact {
+        let schema = format!("{}/schema/{}.json", package, package);
+        Artifact {
+            file: format!("{}/{}", package, file),
+            schema: BTreeMap::from([(schema, "sha256".to_string())]),
+            ..test_artifact(package, "")
+        }
+    }
+
+    #[test]
+    fn stale_outputs_works() {
+        let previous = PreviousManifest::of(
+            &[
+                gzip_artifact("one", "", Some("sha256")),
+                gzip_artifact("one", "debug", Some("sha256")),
+                gzip_artifact("removed", "", Some("sha256")),
+            ],
+            Layout::Flat,
+        );
+        let current = PreviousManifest::of(
+            &[
+                gzip_artifact("one", "", Some("sha256")),
+                artifact("two", ""),
+            ],
+            Layout::Flat,
+        );
+
+        let stale = stale_outputs(&previous, &current, Path::new("artifacts"));
+        assert_eq!(
+            stale,
+            BTreeSet::from([
+                PathBuf::from("artifacts/one-debug.wasm"),
+                PathBuf::from("artifacts/one-debug.wasm.gz"),
+                PathBuf::from("artifacts/one-debug.metadata.json"),
+                PathBuf::from("artifacts/removed.wasm"),
+                PathBuf::from("artifacts/removed.wasm.gz"),
+                PathBuf::from("artifacts/removed.metadata.json"),
+                target_wasm_path("one", "debug"),
+                target_wasm_path("removed", ""),
+            ])
+        );
+
+        // Removed packages take their schema and package checksums with them
+        let previous = PreviousManifest::of(
+            &[
+                schema_artifact("one", "default.wasm"),
+                schema_artifact("removed", "default.wasm"),
+            ],
+            Layout::PerPackage,
+        );
+        let current = PreviousManifest::of(
+            &[schema_artifact("one", "default.wasm")],
+            Layout::PerPackage,
+        );
+
+        let stale = stale_outputs(&previous, &current, Path::new("artifacts"));
+        assert_eq!(
+            stale,
+            BTreeSet::from([
+                PathBuf::from("artifacts/removed/default.wasm"),
+                PathBuf::from("artifacts/removed/default.metadata.json"),
+                PathBuf::from("artifacts/removed/schema/removed.json"),
+                PathBuf::from("artifacts/removed/checksums.txt"),
+                target_wasm_path("removed", ""),
+            ])
+        );
+
+        // Switching back to the flat layout leaves no package checksums or index behind
+        let current = PreviousManifest::of(
+            &[Artifact {
+                schema: BTreeMap::new(),
+                ..artifact("one", "")
+            }],
+            Layout::Flat,
+        );
+
+        let stale = stale_outputs(&previous, &current, Path::new("artifacts"));
+        assert_eq!(
+            stale,
+            BTreeSet::from([
+                PathBuf::from("artifacts/index.json"),
+                PathBuf::from("artifacts/one/default.wasm"),
+                PathBuf::from("artifacts/one/default.metadata.json"),
+                PathBuf::from("artifacts/one/schema/one.json"),
+                PathBuf::from("artifacts/one/checksums.txt"),
+                PathBuf::from("artifacts/removed/default.wasm"),
+                PathBuf::from("artifacts/removed/default.metadata.json"),
+                PathBuf::from("artifacts/removed/schema/removed.json"),
+                PathBuf::from("artifacts/removed/checksums.txt"),
+                target_wasm_path("removed", ""),
+            ])
+        );
+    }
+
+    #[test]
+    fn stale_outputs_works_when_gzip_is_turned_off() {
+        let previous =
+            PreviousManifest::of(&[gzip_artifact("one", "", Some("sha256"))], Layout::Flat);
+        let current = PreviousManifest::of(&[artifact("one", "")], Layout::Flat);
+
+        let stale = stale_outputs(&previous, &current, Path::new("artifacts"));
+        assert_eq!(
+            stale,
+            BTreeSet::from([PathBuf::from("artifacts/one.wasm.gz")])
+        );
+    }
+
+    #[test]
+    fn remove_stale_outputs_works() {
+        let artifacts_dir = env::temp_dir().join(format!("bob-cleanup-{}", std::process::id()));
+        let previous_artifacts = [
+            schema_artifact("one", "default.wasm"),
+            schema_artifact("removed", "default.wasm"),
+        ];
+        for file in [
+            "one/default.wasm",
+            "one/schema/one.json",
+            "removed/default.wasm",
+            "removed/schema/removed.json",
+        ] {
+            fs::create_dir_all(artifacts_dir.join(file).parent().unwrap()).unwrap();
+            fs::write(artifacts_dir.join(file), b"").unwrap();
+        }
+        write_package_index(&artifacts_dir, &previous_artifacts);
+        write_manifest(
+            &artifacts_dir,
+            &previous_artifacts,
+            Timings::default().report(),
+        );
+
+        let previous = previous_manifest(&artifacts_dir);
+        let removed = remove_stale_outputs(
+            &previous,
+            &[schema_artifact("one", "default.wasm")],
+            Layout::PerPackage,
+            &artifacts_dir,
+        );
+        assert_eq!(
+            removed,
+            vec![
+                artifacts_dir.join("removed/checksums.txt"),
+                artifacts_dir.join("removed/default.wasm"),
+                artifacts_dir.join("removed/schema/removed.json"),
+                artifacts_dir.join("removed/schema"),
+                artifacts_dir.join("removed"),
+            ]
+        );
+        assert!(artifacts_dir.join("one/schema/one.json").is_file());
+        assert!(artifacts_dir.join(INDEX_FILE).is_file());
+
+        fs::remove_dir_all(&artifacts_dir).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+use std::{fs, io::Write, path::Path};
+
+use flate2::{Compression, GzBuilder};
+
+use crate::checksum::sha256;
+
+/// Appended to the artifact file name for the compressed artifact
+pub const GZIP_SUFFIX: &str = ".gz";
+
+/// The "unknown" operating system in the gzip header
+const UNKNOWN_OS: u8 = 255;
+
+/// Compresses *data* into a gzip stream that only depends on *data*: no modification time,
+/// no file name, a fixed operating system and the best compression level.
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new()
+        .mtime(0)
+        .operating_system(UNKNOWN_OS)
+        .write(Vec::new(), Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes `<file>.gz` next to the artifact *file* in *artifacts_dir* and returns its SHA-256 hash.
+pub fn write_gzip(artifacts_dir: &Path, file: &str) -> String {
+    let compressed = gzip(&fs::read(artifacts_dir.join(file)).unwrap());
+    fs::write(
+        artifacts_dir.join(format!("{}{}", file, GZIP_SUFFIX)),
+        &compressed,
+    )
+    .expect("Failed to write compressed artifact");
+    sha256(&compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn gzip_works() {
+        let data = b"\0asm\x01\0\0\0".repeat(100);
+        let compressed = gzip(&data);
+        assert_eq!(compressed, gzip(&data));
+
+        // magic, deflate, no flags (so no file name), mtime 0, best compression, unknown OS
+        assert_eq!(
+            compressed[..10],
+            [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, UNKNOWN_OS]
+        );
+
+        let mut decompressed = vec![];
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::cargo_toml::{
+    dependencies::{Dependency, PackageDependencies},
+    package::Feature,
+};
+
+/// The feature our contracts use to disable their entry points when used as a library
+const LIBRARY_FEATURE: &str = "library";
+
+/// A contract of the workspace together with the features each of its builds requests
+#[derive(Debug)]
+pub struct Contract {
+    pub dependencies: PackageDependencies,
+    /// The features passed to cargo per build, including `default` unless the build disables
+    /// the default features
+    pub builds: Vec<BTreeSet<Feature>>,
+}
+
+/// A contract that links the entry points of another contract.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The dependency has a `library` feature but the dependent does not enable it.
+    /// This is an error.
+    MissingLibraryFeature {
+        dependent: String,
+        dependency: String,
+    },
+    /// The dependency has no `library` feature, so its entry points cannot be disabled.
+    /// This is a warning.
+    NoLibraryFeature {
+        dependent: String,
+        dependency: String,
+    },
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Problem::MissingLibraryFeature { .. })
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Problem::MissingLibraryFeature {
+                dependent,
+                dependency,
+            } => format!(
+                "{} depends on contract {} without enabling its \"{}\" feature, so the entry points of both get linked",
+                dependent, dependency, LIBRARY_FEATURE
+            ),
+            Problem::NoLibraryFeature {
+                dependent,
+                dependency,
+            } => format!(
+                "{} depends on contract {}, which has no \"{}\" feature to disable its entry points",
+                dependent, dependency, LIBRARY_FEATURE
+            ),
+        }
+    }
+}
+
+/// Returns the *requested* features of *package* and everything they enable transitively,
+/// including entries like `dep:foo`, `foo/bar` and `foo?/bar`.
+fn enabled_features(
+    package: &PackageDependencies,
+    requested: &BTreeSet<Feature>,
+) -> BTreeSet<String> {
+    let mut enabled = BTreeSet::new();
+    let mut queue = requested.iter().cloned().collect::<Vec<_>>();
+    while let Some(feature) = queue.pop() {
+        if let Some(enables) = package.features.get(&feature) {
+            if !enabled.contains(&feature) {
+                queue.extend(enables.iter().cloned());
+            }
+        }
+        enabled.insert(feature);
+    }
+    enabled
+}
+
+/// Checks if a build with the *enabled* features links the dependency declared as *name*,
+/// i.e. it is not optional or a feature turns it on.
+fn is_linked(declared: &Dependency, name: &str, enabled: &BTreeSet<String>) -> bool {
+    !declared.optional
+        || enabled.contains(name)
+        || enabled.contains(&format!("dep:{}", name))
+        || enabled
+            .iter()
+            .any(|feature| feature.starts_with(&format!("{}/", name)))
+}
+
+/// Finds contracts that depend on other contracts of the workspace without disabling
+/// their entry points. The `library` feature of a dependency counts as enabled in a build if
+/// the dependency declaration enables it or if one of the features of the build does, e.g.
+/// `default = ["other/library"]`. A problem is reported if any build lacks it.
+pub fn find_problems(
+    workspace_dependencies: &BTreeMap<String, Dependency>,
+    contracts: &[Contract],
+) -> Vec<Problem> {
+    let mut problems = vec![];
+    for contract in contracts {
+        let dependent = &contract.dependencies;
+        let builds = contract
+            .builds
+            .iter()
+            .map(|requested| enabled_features(dependent, requested))
+            .collect::<Vec<_>>();
+        for declared in &dependent.dependencies {
+            // Features of `foo = { workspace = true, features = [...] }` are added to
+            // the features of the workspace declaration
+            let inherited = declared
+                .workspace
+                .then(|| workspace_dependencies.get(&declared.name))
+                .flatten();
+            let package = inherited.map_or(&declared.package, |dep| &dep.package);
+            let Some(dependency) = contracts
+                .iter()
+                .map(|c| &c.dependencies)
+                .find(|c| &c.name == package)
+            else {
+                continue;
+            };
+
+            let library_declared = declared.features.contains(LIBRARY_FEATURE)
+                || inherited.is_some_and(|dep| dep.features.contains(LIBRARY_FEATURE));
+            if library_declared {
+                continue;
+            }
+            // Features refer to the dependency by its key, which differs from the package
+            // name for renamed dependencies
+            let name = &declared.name;
+            let library_features = [
+                format!("{}/{}", name, LIBRARY_FEATURE),
+                format!("{}?/{}", name, LIBRARY_FEATURE),
+            ];
+            let links_entry_points = builds.iter().any(|enabled| {
+                is_linked(declared, name, enabled)
+                    && !library_features
+                        .iter()
+                        .any(|feature| enabled.contains(feature))
+            });
+            if !links_entry_points {
+                continue;
+            }
+
+            let names = (dependent.name.clone(), dependency.name.clone());
+            problems.push(if dependency.features.contains_key(LIBRARY_FEATURE) {
+                Problem::MissingLibraryFeature {
+                    dependent: names.0,
+                    dependency: names.1,
+                }
+            } else {
+                Problem::NoLibraryFeature {
+                    dependent: names.0,
+                    dependency: names.1,
+                }
+            });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependency(name: &str, features: &[&str], workspace: bool) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            package: name.replace('-', "_"),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            workspace,
+            optional: false,
+        }
+    }
+
+    fn contract_with_features(
+        name: &str,
+        features: &[(&str, &[&str])],
+        dependencies: Vec<Dependency>,
+    ) -> Contract {
+        Contract {
+            dependencies: PackageDependencies {
+                name: name.to_string(),
+                features: features
+                    .iter()
+                    .map(|(feature, enables)| {
+                        let enables = enables.iter().map(|f| f.to_string()).collect();
+                        (feature.to_string(), enables)
+                    })
+                    .collect(),
+                dependencies,
+            },
+            builds: vec![BTreeSet::from(["default".to_string()])],
+        }
+    }
+
+    fn contract(name: &str, features: &[&str], dependencies: Vec<Dependency>) -> Contract {
+        let features = features
+            .iter()
+            .map(|feature| (*feature, &[][..]))
+            .collect::<Vec<_>>();
+        contract_with_features(name, &features, dependencies)
+    }
+
+    #[test]
+    fn find_problems_works() {
+        let workspace_dependencies = BTreeMap::from([
+            ("base".to_string(), dependency("base", &["library"], false)),
+            ("plain".to_string(), dependency("plain", &[], false)),
+        ]);
+        let contracts = vec![
+            contract("base", &["library"], vec![]),
+            contract("plain", &["library"], vec![]),
+            contract("no_lib", &[], vec![]),
+            contract(
+                "good",
+                &[],
+                vec![
+                    dependency("cosmwasm-std", &[], false),
+                    dependency("base", &[], true),
+                    dependency("plain", &["library"], true),
+                ],
+            ),
+            contract(
+                "bad",
+                &[],
+                vec![
+                    dependency("base", &["other"], false),
+                    dependency("plain", &[], true),
+                    dependency("no-lib", &[], false),
+                ],
+            ),
+        ];
+
+        let problems = find_problems(&workspace_dependencies, &contracts);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::MissingLibraryFeature {
+                    dependent: "bad".to_string(),
+                    dependency: "base".to_string()
+                },
+                Problem::MissingLibraryFeature {
+                    dependent: "bad".to_string(),
+                    dependency: "plain".to_string()
+                },
+                Problem::NoLibraryFeature {
+                    dependent: "bad".to_string(),
+                    dependency: "no_lib".to_string()
+                },
+            ]
+        );
+        assert!(problems[0].is_error());
+        assert!(!problems[2].is_error());
+        assert_eq!(
+            problems[0].message(),
+            "bad depends on contract base without enabling its \"library\" feature, so the entry points of both get linked"
+        );
+    }
+
+    #[test]
+    fn find_problems_considers_features() {
+        let mut optional = dependency("base", &[], false);
+        optional.optional = true;
+        let mut contracts = vec![
+            contract("base", &["library"], vec![]),
+            // Enabled by the default features, also transitively
+            contract_with_features(
+                "by_default",
+                &[("default", &["extra"]), ("extra", &["base/library"])],
+                vec![dependency("base", &[], false)],
+            ),
+            contract_with_features(
+                "weak",
+                &[("default", &["base?/library"])],
+                vec![dependency("base", &[], false)],
+            ),
+            // Never linked, as no feature turns it on
+            contract("unused", &[], vec![optional.clone()]),
+            contract_with_features("optional", &[("default", &["dep:base"])], vec![optional]),
+            // Only enabled when the dependent is a library itself
+            contract_with_features(
+                "forwarding",
+                &[("library", &["base/library"])],
+                vec![dependency("base", &[], false)],
+            ),
+        ];
+        assert_eq!(
+            find_problems(&BTreeMap::new(), &contracts),
+            vec![
+                Problem::MissingLibraryFeature {
+                    dependent: "optional".to_string(),
+                    dependency: "base".to_string()
+                },
+                Problem::MissingLibraryFeature {
+                    dependent: "forwarding".to_string(),
+                    dependency: "base".to_string()
+                },
+            ]
+        );
+
+        // Builds of the dependent as a library are fine, the standard build is not
+        contracts[5].builds = vec![BTreeSet::from(["library".to_string()])];
+        assert_eq!(find_problems(&BTreeMap::new(), &contracts).len(), 1);
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+
+use wasmparser::WasmFeatures;
+
+use crate::cargo_toml::package::CosmWasmTarget;
+
+impl CosmWasmTarget {
+    /// The Wasm proposals the VM of this CosmWasm version accepts on top of Wasm 1.0.
+    ///
+    /// | Proposal                | 1.x | 2.x | 3.x |
+    /// | ----------------------- | --- | --- | --- |
+    /// | saturating float-to-int | yes | yes | yes |
+    /// | multi-value             | yes | yes | yes |
+    /// | sign-ext                | no  | no  | yes |
+    /// | bulk-memory             | no  | no  | yes |
+    /// | reference-types         | no  | yes | yes |
+    /// | simd                    | no  | no  | no  |
+    pub fn wasm_features(self) -> WasmFeatures {
+        let base =
+            WasmFeatures::WASM1 | WasmFeatures::SATURATING_FLOAT_TO_INT | WasmFeatures::MULTI_VALUE;
+        match self {
+            CosmWasmTarget::V1 => base,
+            // Rust enables reference-types by default since 1.82 and wasm-opt has no pass to
+            // lower it, so 1.x artifacts that use it fail validation.
+            CosmWasmTarget::V2 => base | WasmFeatures::REFERENCE_TYPES,
+            CosmWasmTarget::V3 => {
+                base | WasmFeatures::REFERENCE_TYPES
+                    | WasmFeatures::SIGN_EXTENSION
+                    | WasmFeatures::BULK_MEMORY
+            }
+        }
+    }
+
+    /// The wasm-opt passes that rewrite instructions of proposals the VM does not accept
+    /// into equivalent Wasm 1.0 code: sign-ext, and the `memory.copy` and `memory.fill` of
+    /// bulk-memory, which Rust emits by default since 1.87.
+    pub fn lowering_passes(self) -> &'static [&'static str] {
+        match self {
+            CosmWasmTarget::V1 | CosmWasmTarget::V2 => {
+                &["--signext-lowering", "--llvm-memory-copy-fill-lowering"]
+            }
+            CosmWasmTarget::V3 => &[],
+        }
+    }
+}
+
+impl fmt::Display for CosmWasmTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CosmWasmTarget::V1 => write!(f, "1.x"),
+            CosmWasmTarget::V2 => write!(f, "2.x"),
+            CosmWasmTarget::V3 => write!(f, "3.x"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasm_features_works() {
+        for target in [CosmWasmTarget::V1, CosmWasmTarget::V2, CosmWasmTarget::V3] {
+            assert!(!target.wasm_features().contains(WasmFeatures::SIMD));
+        }
+        for target in [CosmWasmTarget::V1, CosmWasmTarget::V2, CosmWasmTarget::V3] {
+            assert!(target
+                .wasm_features()
+                .contains(WasmFeatures::SATURATING_FLOAT_TO_INT | WasmFeatures::MULTI_VALUE));
+        }
+        assert!(!CosmWasmTarget::V1
+            .wasm_features()
+            .contains(WasmFeatures::REFERENCE_TYPES));
+        assert!(!CosmWasmTarget::V2
+            .wasm_features()
+            .contains(WasmFeatures::SIGN_EXTENSION));
+        assert!(CosmWasmTarget::V3
+            .wasm_features()
+            .contains(WasmFeatures::SIGN_EXTENSION | WasmFeatures::BULK_MEMORY));
+    }
+
+    #[test]
+    fn lowering_passes_works() {
+        let passes = ["--signext-lowering", "--llvm-memory-copy-fill-lowering"];
+        assert_eq!(CosmWasmTarget::V1.lowering_passes(), passes);
+        assert_eq!(CosmWasmTarget::V2.lowering_passes(), passes);
+        assert!(CosmWasmTarget::V3.lowering_passes().is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{cargo_toml::package::Feature, timing::Totals, verify::Outcome};
+
+/// How bob reports its progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Readable messages on stdout and stderr
+    #[default]
+    Human,
+    /// One JSON object per event and line on stdout
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!(
+                "Unknown message format {:?}, expected human or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Identifies one build of a package
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BuildId {
+    pub package: String,
+    /// Empty for the standard build
+    pub build: String,
+}
+
+impl fmt::Display for BuildId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.build.is_empty() {
+            write!(f, "Standard build of {}", self.package)
+        } else {
+            write!(f, "Build {:?} of {}", self.build, self.package)
+        }
+    }
+}
+
+/// Something that happened while building
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The member entries of the workspace `Cargo.toml`
+    WorkspaceMembers { members: Vec<String> },
+    /// The root `Cargo.toml` has a workspace key without members
+    NoWorkspaceMembers,
+    /// All package directories matching the workspace members
+    PackagesFound { directories: Vec<String> },
+    /// The package directories of the contracts to build
+    ContractsFound { directories: Vec<String> },
+    /// The builds of a package start
+    PackageStarted { package: String, toolchain: String },
+    /// The inputs of a build did not change, so the artifact was copied from the cache
+    CacheHit {
+        #[serde(flatten)]
+        id: BuildId,
+        file: String,
+    },
+    /// cargo is about to compile a build
+    BuildStarted {
+        #[serde(flatten)]
+        id: BuildId,
+        features: BTreeSet<Feature>,
+    },
+    /// A build was compiled twice and compared. No differences means it is deterministic.
+    DeterminismChecked {
+        #[serde(flatten)]
+        id: BuildId,
+        differences: Vec<String>,
+    },
+    /// `wasm-opt` is about to optimize the cargo output
+    OptimizeStarted {
+        #[serde(flatten)]
+        id: BuildId,
+        file: String,
+        cosmwasm_target: String,
+    },
+    /// The artifact was written, by `wasm-opt` or from its cache
+    OptimizeFinished {
+        #[serde(flatten)]
+        id: BuildId,
+        file: String,
+        cached: bool,
+    },
+    /// The artifact is valid for its CosmWasm target
+    Validated {
+        #[serde(flatten)]
+        id: BuildId,
+        file: String,
+        entry_points: BTreeSet<String>,
+        required_capabilities: BTreeSet<String>,
+    },
+    /// A build produced its artifact or failed with *error*
+    BuildFinished {
+        #[serde(flatten)]
+        id: BuildId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The schema of a package is about to be generated
+    SchemaStarted { package: String },
+    /// An output of a previous run that this run does not produce was removed
+    StaleOutputRemoved { path: String },
+    /// `verify` is about to compare the rebuilt artifacts with the *reference*
+    VerifyStarted { reference: String },
+    /// A rebuilt artifact was compared with its reference
+    ArtifactVerified {
+        #[serde(flatten)]
+        id: BuildId,
+        file: String,
+        outcome: Outcome,
+    },
+    /// An artifact of the reference was not rebuilt, which is a mismatch
+    ReferenceNotRebuilt { file: String },
+    /// Where the time went, at the end of the run
+    Timings {
+        #[serde(flatten)]
+        totals: Totals,
+    },
+    Note {
+        #[serde(flatten)]
+        id: Option<BuildId>,
+        message: String,
+    },
+    Warning {
+        #[serde(flatten)]
+        id: Option<BuildId>,
+        message: String,
+    },
+    Error {
+        #[serde(flatten)]
+        id: Option<BuildId>,
+        message: String,
+    },
+}
+
+/// Where a human readable message goes
+#[derive(Debug, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Event {
+    /// Returns the human readable message of the event, if it has one.
+    fn render(&self) -> Option<(Stream, String)> {
+        let (stream, message) = match self {
+            Event::WorkspaceMembers { members } => (
+                Stream::Stdout,
+                format!("Found workspace member entries: {:?}", members),
+            ),
+            Event::NoWorkspaceMembers => (
+                Stream::Stdout,
+                "Cargo.toml contains a workspace key but has no workspace members".to_string(),
+            ),
+            Event::PackagesFound { directories } => (
+                Stream::Stdout,
+                format!("Package directories: {:?}", directories),
+            ),
+            Event::ContractsFound { directories } => (
+                Stream::Stdout,
+                format!("Contracts to be built: {:?}", directories),
+            ),
+            Event::PackageStarted { package, toolchain } => (
+                Stream::Stdout,
+                format!("Building {:?} with {} ...", package, toolchain),
+            ),
+            Event::CacheHit { id, file } => (
+                Stream::Stdout,
+                format!("{}: inputs unchanged, reusing cached {}", id, file),
+            ),
+            Event::BuildStarted { id, features } => (
+                Stream::Stderr,
+                format!("Building {} with features {:?}", id.package, features),
+            ),
+            Event::DeterminismChecked { id, differences } if differences.is_empty() => {
+                (Stream::Stdout, format!("{} is deterministic", id))
+            }
+            Event::DeterminismChecked { id, differences } => {
+                let mut message = format!(
+                    "{} is not deterministic. Differences between the two builds:",
+                    id
+                );
+                for difference in differences {
+                    message += &format!("\n  {}", difference);
+                }
+                (Stream::Stderr, message)
+            }
+            Event::OptimizeStarted {
+                file,
+                cosmwasm_target,
+                ..
+            } => (
+                Stream::Stderr,
+                format!("Optimizing {} for CosmWasm {} ...", file, cosmwasm_target),
+            ),
+            Event::OptimizeFinished { cached: true, .. } => (
+                Stream::Stderr,
+                "Cargo output unchanged, reusing cached wasm-opt output".to_string(),
+            ),
+            Event::OptimizeFinished { cached: false, .. } => return None,
+            Event::Validated {
+                file,
+                entry_points,
+                required_capabilities,
+                ..
+            } => {
+                let mut message = format!("{} exports entry points: {:?}", file, entry_points);
+                if !required_capabilities.is_empty() {
+                    message += &format!(
+                        "\n{} requires capabilities: {:?}",
+                        file, required_capabilities
+                    );
+                }
+                (Stream::Stdout, message)
+            }
+            Event::BuildFinished { id, error } => (
+                Stream::Stderr,
+                format!("Error: {}: {}", id, error.as_ref()?),
+            ),
+            Event::SchemaStarted { package } => (
+                Stream::Stderr,
+                format!("Generating schema of {} ...", package),
+            ),
+            Event::StaleOutputRemoved { path } => {
+                (Stream::Stdout, format!("Removed stale output {}", path))
+            }
+            Event::VerifyStarted { reference } => {
+                (Stream::Stdout, format!("Comparing with {} ...", reference))
+            }
+            Event::ArtifactVerified { id, file, outcome } => {
+                let build = if id.build.is_empty() {
+                    "standard build".to_string()
+                } else {
+                    format!("build {:?}", id.build)
+                };
+                (
+                    Stream::Stdout,
+                    format!("{} ({}, {}): {}", file, id.package, build, outcome),
+                )
+            }
+            Event::ReferenceNotRebuilt { file } => (
+                Stream::Stdout,
+                format!("{}: MISMATCH (in reference but not rebuilt)", file),
+            ),
+            Event::Timings { totals } => {
+                let mut message = format!("Time spent: {:.3}s\nBy phase:", totals.seconds);
+                for (phase, seconds) in &totals.phases {
+                    message += &format!("\n  {}: {:.3}s", phase, seconds);
+                }
+                message += "\nBy package:";
+                for (package, seconds) in &totals.packages {
+                    message += &format!("\n  {}: {:.3}s", package, seconds);
+                }
+                (Stream::Stdout, message)
+            }
+            Event::Note { message, .. } => (Stream::Stdout, format!("Note: {}", message)),
+            Event::Warning { message, .. } => (Stream::Stderr, format!("Warning: {}", message)),
+            Event::Error { message, .. } => (Stream::Stderr, format!("Error: {}", message)),
+        };
+        Some((stream, message))
+    }
+}
+
+/// Reports *event* in the given *format*.
+pub fn emit(format: MessageFormat, event: &Event) {
+    match format {
+        MessageFormat::Json => println!("{}", serde_json::to_string(event).unwrap()),
+        MessageFormat::Human => match event.render() {
+            Some((Stream::Stdout, message)) => println!("{}", message),
+            Some((Stream::Stderr, message)) => eprintln!("{}", message),
+            None => {}
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_serialize_as_json_lines() {
+        let id = BuildId {
+            package: "my_contract".to_string(),
+            build: "debug".to_string(),
+        };
+        let event = Event::BuildFinished {
+            id: id.clone(),
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"build_finished","package":"my_contract","build":"debug"}"#
+        );
+        let event = Event::Warning {
+            id: None,
+            message: "careful".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"warning","message":"careful"}"#
+        );
+        let event = Event::Error {
+            id: Some(id.clone()),
+            message: "failed".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"error","package":"my_contract","build":"debug","message":"failed"}"#
+        );
+        let event = Event::ArtifactVerified {
+            id,
+            file: "my_contract-debug.wasm".to_string(),
+            outcome: Outcome::OptimizationDiffers,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"artifact_verified","package":"my_contract","build":"debug","file":"my_contract-debug.wasm","outcome":"optimization_differs"}"#
+        );
+    }
+
+    #[test]
+    fn render_works() {
+        let id = BuildId {
+            package: "my_contract".to_string(),
+            build: String::new(),
+        };
+        assert_eq!(
+            Event::DeterminismChecked {
+                id: id.clone(),
+                differences: vec!["section data differs".to_string()],
+            }
+            .render(),
+            Some((
+                Stream::Stderr,
+                "Standard build of my_contract is not deterministic. Differences between the two builds:\n  section data differs".to_string()
+            ))
+        );
+        assert_eq!(
+            Event::BuildFinished {
+                id: id.clone(),
+                error: None
+            }
+            .render(),
+            None
+        );
+        assert_eq!(
+            Event::ArtifactVerified {
+                id: id.clone(),
+                file: "my_contract.wasm".to_string(),
+                outcome: Outcome::Match,
+            }
+            .render(),
+            Some((
+                Stream::Stdout,
+                "my_contract.wasm (my_contract, standard build): match".to_string()
+            ))
+        );
+        assert_eq!(
+            Event::BuildFinished {
+                id,
+                error: Some("cargo build failed".to_string())
+            }
+            .render(),
+            Some((
+                Stream::Stderr,
+                "Error: Standard build of my_contract: cargo build failed".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Where `optimize.sh` expects the project to be mounted in the Docker image
+const CODE_MOUNT: &str = "/code";
+
+/// Directories that commonly appear in absolute paths of a build host
+const COMMON_HOST_PREFIXES: &[&str] = &[
+    "/code/",
+    "/home/",
+    "/root/",
+    "/Users/",
+    "/usr/local/",
+    "/target/",
+    "/tmp/",
+];
+
+/// Longest path we extract when reporting an absolute path found in an artifact
+const MAX_PATH_LEN: usize = 200;
+
+/// The directories of the build host which may end up in the artifacts
+/// and what we replace them with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostPaths {
+    /// The directory containing all sources of the project, see `project_root`
+    pub project_root: PathBuf,
+    pub cargo_home: PathBuf,
+    pub rustup_home: PathBuf,
+}
+
+impl HostPaths {
+    /// Gets the host paths from the current working directory and the environment,
+    /// using the same defaults as cargo and rustup. Detect them once per run, as they
+    /// do not change.
+    pub fn detect() -> Self {
+        let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        let from_env = |var: &str, default: &str| {
+            env::var_os(var)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(default))
+        };
+        HostPaths {
+            project_root: project_root(&fs::canonicalize(".").unwrap()),
+            cargo_home: from_env("CARGO_HOME", ".cargo"),
+            rustup_home: from_env("RUSTUP_HOME", ".rustup"),
+        }
+    }
+
+    /// Returns the `--remap-path-prefix` rustc flags that replace the host paths with
+    /// fixed values. rustc uses the last matching flag, so longer prefixes come last in
+    /// order to win over shorter ones containing them.
+    pub fn remap_flags(&self) -> Vec<String> {
+        let mut mappings = vec![
+            (&self.project_root, "."),
+            (&self.cargo_home, "/cargo"),
+            (&self.rustup_home, "/rustup"),
+        ];
+        mappings.sort_by_key(|(from, _)| from.as_os_str().len());
+        mappings
+            .into_iter()
+            .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+            .collect()
+    }
+
+    /// Returns the prefixes of absolute paths that should not be found in an artifact
+    fn prefixes(&self) -> BTreeSet<String> {
+        let mut prefixes = [&self.project_root, &self.cargo_home, &self.rustup_home]
+            .iter()
+            .filter(|path| path.parent().is_some()) // skip "/"
+            .map(|path| format!("{}/", path.display()))
+            .collect::<BTreeSet<_>>();
+        prefixes.extend(COMMON_HOST_PREFIXES.iter().map(|p| p.to_string()));
+        prefixes
+    }
+
+    /// Returns the absolute host paths contained in the Wasm bytecode.
+    pub fn find_in(&self, wasm: &[u8]) -> BTreeSet<String> {
+        find_paths(wasm, &self.prefixes())
+    }
+}
+
+/// Returns the directory of the project sources containing *dir*: the `/code` mount of the
+/// Docker image, such that path dependencies next to a contract built from a subdirectory are
+/// covered, or else the outermost directory above *dir* with a `Cargo.toml`, like a workspace
+/// root. Falls back to *dir*.
+fn project_root(dir: &Path) -> PathBuf {
+    if dir.starts_with(CODE_MOUNT) {
+        return PathBuf::from(CODE_MOUNT);
+    }
+    dir.ancestors()
+        .filter(|ancestor| ancestor.join("Cargo.toml").is_file())
+        .last()
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+fn is_path_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"/._-+@~".contains(&byte)
+}
+
+/// Finds strings starting with one of the *prefixes* that are not part of a longer path.
+fn find_paths(data: &[u8], prefixes: &BTreeSet<String>) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    for prefix in prefixes {
+        let prefix = prefix.as_bytes();
+        let mut start = 0;
+        while let Some(pos) = find(&data[start..], prefix).map(|p| p + start) {
+            start = pos + prefix.len();
+            if pos > 0 && is_path_char(data[pos - 1]) {
+                continue;
+            }
+            let len = data[pos..]
+                .iter()
+                .take(MAX_PATH_LEN)
+                .take_while(|b| is_path_char(**b))
+                .count();
+            found.insert(String::from_utf8_lossy(&data[pos..pos + len]).to_string());
+        }
+    }
+    found
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_paths() -> HostPaths {
+        HostPaths {
+            project_root: PathBuf::from("/code"),
+            cargo_home: PathBuf::from("/usr/local/cargo"),
+            rustup_home: PathBuf::from("/usr/local/rustup"),
+        }
+    }
+
+    #[test]
+    fn remap_flags_works() {
+        assert_eq!(
+            host_paths().remap_flags(),
+            vec![
+                "--remap-path-prefix=/code=.",
+                "--remap-path-prefix=/usr/local/cargo=/cargo",
+                "--remap-path-prefix=/usr/local/rustup=/rustup",
+            ]
+        );
+
+        // A cargo home inside of the project must win over the project root
+        let nested = HostPaths {
+            project_root: PathBuf::from("/home/me"),
+            cargo_home: PathBuf::from("/home/me/.cargo"),
+            rustup_home: PathBuf::from("/home/me/.rustup"),
+        };
+        assert_eq!(
+            nested.remap_flags(),
+            vec![
+                "--remap-path-prefix=/home/me=.",
+                "--remap-path-prefix=/home/me/.cargo=/cargo",
+                "--remap-path-prefix=/home/me/.rustup=/rustup",
+            ]
+        );
+    }
+
+    #[test]
+    fn project_root_works() {
+        assert_eq!(
+            project_root(Path::new("/code/contracts/hackatom")),
+            PathBuf::from("/code")
+        );
+        assert_eq!(project_root(Path::new("/code")), PathBuf::from("/code"));
+
+        let workspace = env::temp_dir().join(format!("bob-project-root-{}", std::process::id()));
+        let contract = workspace.join("contracts/my_contract");
+        fs::create_dir_all(&contract).unwrap();
+        assert_eq!(project_root(&contract), contract);
+        fs::write(contract.join("Cargo.toml"), "").unwrap();
+        assert_eq!(project_root(&contract), contract);
+        fs::write(workspace.join("Cargo.toml"), "").unwrap();
+        assert_eq!(project_root(&contract), workspace);
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn find_in_works() {
+        let data = b"\0panicked at /usr/local/cargo/registry/src/serde-1.0.0/src/de.rs\0\
+            ./src/contract.rs\0/cargo/registry/src/foo.rs\0https://example.com/home/\0\
+            /code/packages/std/src/lib.rs:12";
+        assert_eq!(
+            host_paths().find_in(data),
+            BTreeSet::from([
+                "/code/packages/std/src/lib.rs".to_string(),
+                "/usr/local/cargo/registry/src/serde-1.0.0/src/de.rs".to_string(),
+            ])
+        );
+
+        assert!(host_paths().find_in(b"nothing to see here").is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use crate::{
+    naming::STANDARD_BUILD_NAME,
+    pkg_build::{BuildError, BuildResult, CheckFailure},
+};
+
+/// Test suite of the checks that run before compiling anything
+const CHECKS_SUITE: &str = "bob";
+
+/// A build or a failed check
+struct TestCase<'a> {
+    name: &'a str,
+    duration: Duration,
+    error: Option<&'a BuildError>,
+}
+
+/// Escapes *text* for use in XML attributes and text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0, e.g. the escape sequences of colored output
+            c if c.is_control() && !matches!(c, '\n' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// Renders a JUnit XML report with one test suite per package and one test case per build.
+/// Failed builds carry the error and the end of the cargo output. If a check before compiling
+/// stopped the run, it is the only test case, in the test suite `bob`.
+pub fn render(results: &Result<Vec<BuildResult>, CheckFailure>) -> String {
+    let mut packages = BTreeMap::<&str, Vec<TestCase>>::new();
+    match results {
+        Ok(results) => {
+            for result in results {
+                packages.entry(&result.package).or_default().push(TestCase {
+                    name: if result.build.is_empty() {
+                        STANDARD_BUILD_NAME
+                    } else {
+                        &result.build
+                    },
+                    duration: result.duration,
+                    error: result.outcome.as_ref().err(),
+                });
+            }
+        }
+        Err(failure) => packages.entry(CHECKS_SUITE).or_default().push(TestCase {
+            name: failure.check,
+            duration: Duration::ZERO,
+            error: Some(&failure.error),
+        }),
+    }
+    let failures = |cases: &[&TestCase]| cases.iter().filter(|case| case.error.is_some()).count();
+    let time = |cases: &[&TestCase]| seconds(cases.iter().map(|case| case.duration).sum());
+
+    let all = packages.values().flatten().collect::<Vec<_>>();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!(
+        "<testsuites name=\"bob\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+        all.len(),
+        failures(&all),
+        time(&all)
+    );
+    for (package, cases) in &packages {
+        let cases = cases.iter().collect::<Vec<_>>();
+        xml += &format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+            escape(package),
+            cases.len(),
+            failures(&cases),
+            time(&cases)
+        );
+        for case in cases {
+            xml += &format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{}\"",
+                escape(package),
+                escape(case.name),
+                seconds(case.duration)
+            );
+            match case.error {
+                None => xml += "/>\n",
+                Some(err) => {
+                    xml += &format!(
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        escape(&err.message),
+                        escape(&err.output.join("\n"))
+                    );
+                }
+            }
+        }
+        xml += "  </testsuite>\n";
+    }
+    xml += "</testsuites>\n";
+    xml
+}
+
+/// Writes the JUnit XML report of the *results* to *path*.
+pub fn write_junit(path: &Path, results: &Result<Vec<BuildResult>, CheckFailure>) {
+    fs::write(path, render(results)).expect("Failed to write JUnit report");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::report::{test_artifact, Artifact};
+
+    fn result(build: &str, outcome: Result<Artifact, BuildError>) -> BuildResult {
+        BuildResult {
+            package: "my_contract".to_string(),
+            build: build.to_string(),
+            duration: Duration::from_millis(1500),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn render_works() {
+        let artifact = test_artifact("my_contract", "");
+
+        let results = Ok(vec![
+            result("", Ok(artifact)),
+            result(
+                "debug",
+                Err(BuildError {
+                    message: "cargo build failed (exit status: 101)".to_string(),
+                    output: vec![
+                        "error[E0425]: cannot find value `x` in this scope".to_string(),
+                        "\u{1b}[1m<x> & \"y\"".to_string(),
+                    ],
+                }),
+            ),
+        ]);
+
+        assert_eq!(
+            render(&results),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="bob" tests="2" failures="1" time="3.000">
+  <testsuite name="my_contract" tests="2" failures="1" time="3.000">
+    <testcase classname="my_contract" name="default" time="1.500"/>
+    <testcase classname="my_contract" name="debug" time="1.500">
+      <failure message="cargo build failed (exit status: 101)">error[E0425]: cannot find value `x` in this scope
+[1m&lt;x&gt; &amp; &quot;y&quot;</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn render_works_for_failed_checks() {
+        let results = Err(CheckFailure {
+            check: "lockfiles",
+            error: BuildError {
+                message: "Found 1 problems with Cargo.lock".to_string(),
+                output: vec!["Cargo.lock is missing".to_string()],
+            },
+        });
+
+        assert_eq!(
+            render(&results),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="bob" tests="1" failures="1" time="0.000">
+  <testsuite name="bob" tests="1" failures="1" time="0.000">
+    <testcase classname="bob" name="lockfiles" time="0.000">
+      <failure message="Found 1 problems with Cargo.lock">Cargo.lock is missing</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -1,20 +1,122 @@
+mod analysis;
+mod bundle;
+mod cache;
 mod cargo_toml;
+mod checksum;
+mod cleanup;
+mod compress;
+mod contract_deps;
+mod cosmwasm_target;
+mod events;
+mod host_paths;
+mod junit;
+mod naming;
+mod optimize;
 mod pkg_build;
+mod preflight;
+mod provenance;
+mod report;
+mod schema;
+mod summary;
+mod timing;
+mod toolchain;
+mod verify;
+mod wasm_diff;
 
 use glob::glob;
 use std::{
     fs::{self},
     path::{Path, PathBuf},
+    process::Command,
 };
 
 use cargo_toml::{
+    dependencies,
     package::{self},
-    workspace::{is_workspace, IsWorkspace},
+    workspace::{is_workspace, parse_settings, IsWorkspace, WorkspaceSettings},
 };
+use events::Event;
+use host_paths::HostPaths;
+use pkg_build::{BuildError, BuildResult, CheckFailure, ParsedPackage};
+use provenance::{write_provenance, Statement};
+use report::{
+    write_checksums, write_manifest, write_package_index, Artifact, CHECKSUMS_FILE, MANIFEST_FILE,
+};
+use timing::{Phase, Timings};
+use toolchain::Toolchain;
+
+pub use events::MessageFormat;
+pub use naming::Layout;
+pub use toolchain::ToolchainPolicy;
 
 const CARGO_PATH: &str = "cargo";
+const RUSTC_PATH: &str = "rustc";
 const PACKAGE_PREFIX: &str = "contracts/";
 
+/// Where `verify` puts the artifacts of the rebuild
+const VERIFY_ARTIFACTS_DIR: &str = "/target/verify-artifacts";
+
+/// Settings given on the command line
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Where the optimized artifacts and reports are written to
+    pub artifacts_dir: PathBuf,
+    /// Build everything twice from a clean target directory and fail if the results differ
+    pub check_determinism: bool,
+    /// Run all cargo commands without network access
+    pub offline: bool,
+    /// How to handle toolchain files requesting a different toolchain than the active one
+    pub toolchain_policy: ToolchainPolicy,
+    /// Rebuild everything instead of reusing artifacts of previous runs with the same inputs
+    pub no_cache: bool,
+    /// Run the schema binary of each package and put the schema next to the artifacts
+    pub schema: bool,
+    /// Template for the artifact file names, overriding the settings in `Cargo.toml`
+    pub name_template: Option<String>,
+    /// How the artifacts are arranged in the artifacts directory
+    pub layout: Layout,
+    /// Write a deterministic `.wasm.gz` next to each artifact
+    pub gzip: bool,
+    /// Also write the checksums of the cargo outputs before `wasm-opt`
+    pub intermediate_checksums: bool,
+    /// Where to write a JUnit XML report with one test case per build
+    pub junit: Option<PathBuf>,
+    /// Where to write a Markdown summary of the artifacts
+    pub summary: Option<PathBuf>,
+    /// `manifest.json` of a previous run to compare the artifact sizes with in the summary
+    pub previous_manifest: Option<PathBuf>,
+    /// How progress is reported
+    pub message_format: MessageFormat,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            artifacts_dir: PathBuf::from("artifacts"),
+            check_determinism: false,
+            offline: false,
+            toolchain_policy: ToolchainPolicy::default(),
+            no_cache: false,
+            schema: false,
+            name_template: None,
+            layout: Layout::default(),
+            gzip: false,
+            intermediate_checksums: false,
+            junit: None,
+            summary: None,
+            previous_manifest: None,
+            message_format: MessageFormat::default(),
+        }
+    }
+}
+
+impl Options {
+    /// Reports *event* in the message format of the options.
+    fn emit(&self, event: Event) {
+        events::emit(self.message_format, &event);
+    }
+}
+
 /// Checks if the given path is a Cargo project. This is needed
 /// to filter the glob results of a workspace member like `contracts/*`
 /// to exclude things like non-directories.
@@ -26,24 +128,402 @@ fn is_cargo_project(path: &PathBuf) -> bool {
     path.is_dir()
 }
 
-pub fn build() {
+/// Builds the project in the current directory and writes the optimized artifacts
+/// together with their metadata to the artifacts directory. Outputs of the previous run
+/// that are no longer built are removed.
+pub fn build(options: &Options) {
+    let mut timings = Timings::default();
+    let previous = cleanup::previous_manifest(&options.artifacts_dir);
+    // Read before the manifest gets overwritten, which it might be
+    let previous_sizes = options
+        .previous_manifest
+        .as_deref()
+        .map(summary::read_previous_sizes);
+    let results = build_artifacts(options, &mut timings);
+    if let Some(junit) = &options.junit {
+        junit::write_junit(junit, &results);
+    }
+    let artifacts = match successful_artifacts(results) {
+        Ok(artifacts) => artifacts,
+        Err(message) => {
+            // Where the time went is most interesting for failed runs, e.g. timeouts in CI
+            options.emit(Event::Timings {
+                totals: timings.totals(),
+            });
+            panic!("{}", message);
+        }
+    };
+    for path in cleanup::remove_stale_outputs(
+        &previous,
+        &artifacts,
+        options.layout,
+        &options.artifacts_dir,
+    ) {
+        options.emit(Event::StaleOutputRemoved {
+            path: path.display().to_string(),
+        });
+    }
+    if artifacts.is_empty() {
+        options.emit(Event::Warning {
+            id: None,
+            message: "No .wasm file built. Check your build configuration in Cargo.toml."
+                .to_string(),
+        });
+    }
+    timings.measure(None, None, Phase::Checksums, || {
+        write_checksums(
+            &options.artifacts_dir,
+            &artifacts,
+            options.intermediate_checksums,
+        );
+        if options.layout == Layout::PerPackage {
+            write_package_index(&options.artifacts_dir, &artifacts);
+        }
+    });
+    write_manifest(&options.artifacts_dir, &artifacts, timings.report());
+    if let Some(summary) = &options.summary {
+        summary::write_summary(summary, &artifacts, previous_sizes.as_ref());
+    }
+
+    let cargo_lock = fs::read("Cargo.lock").unwrap();
+    let statement = Statement::new(
+        &artifacts,
+        &cargo_lock,
+        Toolchain::active().rustc_version,
+        optimize::version(),
+    );
+    write_provenance(&options.artifacts_dir, &statement);
+
+    options.emit(Event::Timings {
+        totals: timings.totals(),
+    });
+}
+
+/// Downloads all dependencies of the project in the current directory into the registry cache,
+/// such that everything can be built with the `offline` option afterwards.
+pub fn fetch() {
+    // Without `--target`, cargo fetches the dependencies of all platforms, which includes the
+    // host dependencies of build scripts, proc macros and the schema binary
+    let status = Command::new(CARGO_PATH)
+        .args(["fetch", "--locked"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Packs the artifacts directory into a reproducible tarball at *output*, which defaults to
+/// `<artifacts_dir>.tar.gz`. Returns the SHA-256 hash of the bundle.
+pub fn bundle(options: &Options, output: Option<&Path>) -> String {
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| bundle::default_output(&options.artifacts_dir));
+    let bundle = bundle::bundle(&options.artifacts_dir, &output);
+    fs::write(&output, &bundle).expect("Failed to write bundle");
+    let sha256 = checksum::sha256(&bundle);
+    println!("{}  {}", sha256, output.display());
+    sha256
+}
+
+/// Returns a cargo command that runs with the given *toolchain* and cannot access the network
+/// in offline mode.
+fn cargo_command(options: &Options, toolchain: &Toolchain) -> Command {
+    let mut command = Command::new(CARGO_PATH);
+    command.env("RUSTUP_TOOLCHAIN", &toolchain.name);
+    if options.offline {
+        command.env("CARGO_NET_OFFLINE", "true");
+    }
+    command
+}
+
+/// Returns the trimmed output of `<program> --version`.
+fn tool_version(program: &str) -> String {
+    let output = Command::new(program).arg("--version").output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Rebuilds the project in the current directory and compares the artifacts with the
+/// checksums in *reference*, which is either a `checksums.txt` or a `manifest.json`.
+/// Defaults to the `checksums.txt` in the artifacts directory, or to the `manifest.json`
+/// if there is none as in the per-package layout.
+///
+/// Returns true if all artifacts match.
+pub fn verify(options: &Options, reference: Option<&Path>) -> bool {
+    let reference_path = reference.map(Path::to_path_buf).unwrap_or_else(|| {
+        let checksums = options.artifacts_dir.join(CHECKSUMS_FILE);
+        if checksums.exists() {
+            checksums
+        } else {
+            options.artifacts_dir.join(MANIFEST_FILE)
+        }
+    });
+    let reference = verify::load_reference(&reference_path);
+
+    // Keep the artifacts we verify untouched and really rebuild them
+    let options = Options {
+        artifacts_dir: PathBuf::from(VERIFY_ARTIFACTS_DIR),
+        no_cache: true,
+        ..options.clone()
+    };
+    if options.artifacts_dir.exists() {
+        fs::remove_dir_all(&options.artifacts_dir).unwrap();
+    }
+    fs::create_dir_all(&options.artifacts_dir).unwrap();
+
+    let artifacts = successful_artifacts(build_artifacts(&options, &mut Timings::default()))
+        .unwrap_or_else(|message| panic!("{}", message));
+    options.emit(Event::VerifyStarted {
+        reference: reference_path.display().to_string(),
+    });
+    verify::report(&artifacts, &reference, &options)
+}
+
+/// Returns the artifacts of the *results*, or why the run failed. The failures were reported
+/// when they happened.
+fn successful_artifacts(
+    results: Result<Vec<BuildResult>, CheckFailure>,
+) -> Result<Vec<Artifact>, String> {
+    let (artifacts, failures): (Vec<_>, Vec<_>) = results
+        .map_err(|failure| failure.error.message)?
+        .into_iter()
+        .map(|result| result.outcome)
+        .partition(Result::is_ok);
+    if !failures.is_empty() {
+        return Err(format!("{} builds failed", failures.len()));
+    }
+    Ok(artifacts.into_iter().map(Result::unwrap).collect())
+}
+
+fn build_artifacts(
+    options: &Options,
+    timings: &mut Timings,
+) -> Result<Vec<BuildResult>, CheckFailure> {
     let file = fs::read_to_string("Cargo.toml").unwrap();
+    let host_paths = HostPaths::detect();
     match is_workspace(&file).unwrap() {
         IsWorkspace::Yes { members } => {
-            println!("Found workspace member entries: {:?}", &members);
-            build_workspace(&members);
+            options.emit(Event::WorkspaceMembers {
+                members: members.clone(),
+            });
+            build_workspace(&members, options, &host_paths, timings)
         }
         IsWorkspace::NoMembers => {
-            println!("Cargo.toml contains a workspace key but has no workspace members");
+            options.emit(Event::NoWorkspaceMembers);
+            Ok(vec![])
         }
         IsWorkspace::No => {
-            let package = package::parse_toml(&file).unwrap();
-            package.build(Path::new("."))
+            let package = timings.measure(None, None, Phase::ParseManifests, || {
+                parse_package(Path::new("."), options)
+            });
+            check_artifact_files(&[&package], options)?;
+            let toolchains = resolve_toolchains(&[Path::new(".")], options)?;
+            check_lockfiles(&[], options)?;
+            Ok(package.build(
+                Path::new("."),
+                options,
+                &toolchains[0],
+                &host_paths,
+                timings,
+            ))
         }
     }
 }
 
-pub fn build_workspace(workspace_members: &[String]) {
+/// Parses the `Cargo.toml` in *dir*, applying the workspace settings and the options.
+fn parse_package(dir: &Path, options: &Options) -> ParsedPackage {
+    let WorkspaceSettings {
+        version,
+        name_template,
+    } = parse_settings(&fs::read_to_string("Cargo.toml").unwrap()).unwrap();
+    let mut package =
+        package::parse_toml(&fs::read_to_string(dir.join("Cargo.toml")).unwrap()).unwrap();
+    package.version = package.version.or(version);
+    package.name_template = options
+        .name_template
+        .clone()
+        .or(package.name_template)
+        .or(name_template);
+    package
+}
+
+/// Reports the *problems* found by *check* as errors and fails the check with *summary*
+/// if there are any.
+fn check_result(
+    check: &'static str,
+    problems: Vec<String>,
+    summary: impl FnOnce(usize) -> String,
+    options: &Options,
+) -> Result<(), CheckFailure> {
+    for problem in &problems {
+        options.emit(Event::Error {
+            id: None,
+            message: problem.clone(),
+        });
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(CheckFailure {
+        check,
+        error: BuildError {
+            message: summary(problems.len()),
+            output: problems,
+        },
+    })
+}
+
+/// Stops before compiling anything if an artifact file name cannot be created or two artifacts
+/// of the *packages* get the same name. All problems are reported at once.
+fn check_artifact_files(
+    packages: &[&ParsedPackage],
+    options: &Options,
+) -> Result<(), CheckFailure> {
+    let mut problems = vec![];
+    let mut files = vec![];
+    for package in packages {
+        for build in package.all_builds() {
+            match package.artifact_file(&build, options.layout) {
+                Ok(file) => files.push((file, build.describe(&package.name))),
+                Err(err) => problems.push(format!("{}: {}", build.describe(&package.name), err)),
+            }
+        }
+    }
+    problems.extend(naming::find_duplicates(&files));
+    check_result(
+        "artifact_names",
+        problems,
+        |count| format!("Found {} problems with artifact names", count),
+        options,
+    )
+}
+
+/// Determines the toolchain for each of the *packages* from the toolchain files in the project.
+/// Stops before compiling anything if one of them cannot be built. All problems are reported
+/// at once.
+fn resolve_toolchains(
+    packages: &[&Path],
+    options: &Options,
+) -> Result<Vec<Toolchain>, CheckFailure> {
+    let active = Toolchain::active();
+    let (resolved, problems): (Vec<_>, Vec<_>) = packages
+        .iter()
+        .map(|dir| toolchain::resolve(Path::new("."), dir, &active, options.toolchain_policy))
+        .partition(Result::is_ok);
+    check_result(
+        "toolchains",
+        problems.into_iter().map(Result::unwrap_err).collect(),
+        |count| format!("Found {} problems with the Rust toolchain", count),
+        options,
+    )?;
+    Ok(resolved
+        .into_iter()
+        .map(Result::unwrap)
+        .map(|(toolchain, warning)| {
+            if let Some(message) = warning {
+                options.emit(Event::Warning { id: None, message });
+            }
+            toolchain
+        })
+        .collect())
+}
+
+/// Stops before compiling anything if `Cargo.lock` is missing or outdated for the
+/// project root or one of the *packages*. All problems are reported at once.
+fn check_lockfiles(packages: &[&Path], options: &Options) -> Result<(), CheckFailure> {
+    let problems =
+        preflight::check_lockfiles(Path::new("."), packages, options, &Toolchain::active());
+    check_result(
+        "lockfiles",
+        problems,
+        |count| format!("Found {} problems with Cargo.lock", count),
+        options,
+    )
+}
+
+/// Ensures no build of the *packages* links the entry points of another contract in the
+/// workspace, which happens when depending on a contract without its `library` feature.
+fn check_contract_dependencies(
+    contract_packages: &[&PathBuf],
+    packages: &[ParsedPackage],
+    options: &Options,
+) -> Result<(), CheckFailure> {
+    let workspace_dependencies =
+        dependencies::parse_workspace_toml(&fs::read_to_string("Cargo.toml").unwrap())
+            .map_err(|err| format!("Failed to parse Cargo.toml: {}", err));
+    let (contracts, invalid): (Vec<_>, Vec<_>) = contract_packages
+        .iter()
+        .zip(packages)
+        .map(|(contract_dir, package)| {
+            let path = contract_dir.join("Cargo.toml");
+            let dependencies = dependencies::parse_toml(&fs::read_to_string(&path).unwrap())
+                .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+            let builds = package
+                .all_builds()
+                .into_iter()
+                .map(|build| {
+                    let mut features = build.settings.features.unwrap_or_default();
+                    if build.settings.default_features != Some(false) {
+                        features.insert("default".to_string());
+                    }
+                    features
+                })
+                .collect();
+            Ok(contract_deps::Contract {
+                dependencies,
+                builds,
+            })
+        })
+        .partition(Result::is_ok);
+    let invalid = workspace_dependencies
+        .as_ref()
+        .err()
+        .cloned()
+        .into_iter()
+        .chain(invalid.into_iter().map(Result::unwrap_err))
+        .collect();
+    check_result(
+        "contract_dependencies",
+        invalid,
+        |count| format!("Found {} invalid dependency declarations", count),
+        options,
+    )?;
+    let workspace_dependencies = workspace_dependencies.unwrap();
+    let contracts = contracts
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+
+    let problems = contract_deps::find_problems(&workspace_dependencies, &contracts);
+    for problem in problems.iter().filter(|p| !p.is_error()) {
+        options.emit(Event::Warning {
+            id: None,
+            message: problem.message(),
+        });
+    }
+    let errors = problems
+        .iter()
+        .filter(|p| p.is_error())
+        .map(|p| p.message())
+        .collect();
+    check_result(
+        "contract_dependencies",
+        errors,
+        |count| {
+            format!(
+                "Found {} contract dependencies linking another contract's entry points",
+                count
+            )
+        },
+        options,
+    )
+}
+
+pub fn build_workspace(
+    workspace_members: &[String],
+    options: &Options,
+    host_paths: &HostPaths,
+    timings: &mut Timings,
+) -> Result<Vec<BuildResult>, CheckFailure> {
     let mut all_packages = workspace_members
         .iter()
         .flat_map(|member| {
@@ -56,19 +536,49 @@ pub fn build_workspace(workspace_members: &[String]) {
 
     all_packages.sort();
 
-    println!("Package directories: {:?}", all_packages);
+    options.emit(Event::PackagesFound {
+        directories: all_packages
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect(),
+    });
 
     let contract_packages = all_packages
         .iter()
         .filter(|p| p.starts_with(PACKAGE_PREFIX))
         .collect::<Vec<_>>();
 
-    println!("Contracts to be built: {:?}", contract_packages);
+    options.emit(Event::ContractsFound {
+        directories: contract_packages
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect(),
+    });
+
+    let contract_dirs = contract_packages
+        .iter()
+        .map(|dir| dir.as_path())
+        .collect::<Vec<_>>();
+    let packages = timings.measure(None, None, Phase::ParseManifests, || {
+        contract_dirs
+            .iter()
+            .map(|dir| parse_package(dir, options))
+            .collect::<Vec<_>>()
+    });
+    check_contract_dependencies(&contract_packages, &packages, options)?;
+    check_artifact_files(&packages.iter().collect::<Vec<_>>(), options)?;
+    let toolchains = resolve_toolchains(&contract_dirs, options)?;
+    check_lockfiles(&contract_dirs, options)?;
 
-    for contract_dir in contract_packages {
-        let contract_cargo_toml = fs::read_to_string(contract_dir.join("Cargo.toml")).unwrap();
-        let package = package::parse_toml(&contract_cargo_toml).unwrap();
-        println!("Building {:?} ...", package.name);
-        package.build(contract_dir);
+    let mut results = vec![];
+    for ((contract_dir, package), toolchain) in
+        contract_packages.into_iter().zip(packages).zip(&toolchains)
+    {
+        options.emit(Event::PackageStarted {
+            package: package.name.clone(),
+            toolchain: toolchain.to_string(),
+        });
+        results.extend(package.build(contract_dir, options, toolchain, host_paths, timings));
     }
+    Ok(results)
 }
@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use crate::cargo_toml::package::Feature;
+
+/// The template giving the traditional names `<package>.wasm` and `<package>-<build>.wasm`
+pub const DEFAULT_NAME_TEMPLATE: &str = "{package}-{build}";
+
+/// Name of the standard build in the per-package layout without a name template and in reports
+pub const STANDARD_BUILD_NAME: &str = "default";
+
+/// How the artifacts are arranged in the artifacts directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// All artifacts directly in the artifacts directory
+    #[default]
+    Flat,
+    /// `<package>/<build>.wasm` with a `checksums.txt` per package
+    PerPackage,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Layout::Flat),
+            "per-package" => Ok(Layout::PerPackage),
+            _ => Err(format!(
+                "Unknown layout {:?}, expected flat or per-package",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the path of an artifact relative to the artifacts directory, without `.wasm`.
+/// The *template* defaults to `{package}-{build}` in the flat layout and to the build name
+/// (`default` for the standard build) in the per-package layout.
+pub fn artifact_name(
+    layout: Layout,
+    template: Option<&str>,
+    values: &NameValues,
+) -> Result<String, String> {
+    let name = match (layout, template) {
+        (Layout::PerPackage, None) if values.build.is_empty() => STANDARD_BUILD_NAME.to_string(),
+        (Layout::PerPackage, None) => values.build.to_string(),
+        (_, template) => render(template.unwrap_or(DEFAULT_NAME_TEMPLATE), values)?,
+    };
+    Ok(match layout {
+        Layout::Flat => name,
+        Layout::PerPackage => format!("{}/{}", values.package, name),
+    })
+}
+
+/// Characters dropped in front of a placeholder that expands to nothing
+const SEPARATORS: &[char] = &['-', '_', '@', '.'];
+
+/// The values a name template can refer to
+pub struct NameValues<'a> {
+    pub package: &'a str,
+    pub version: Option<&'a str>,
+    /// Empty for the standard build
+    pub build: &'a str,
+    pub features: &'a BTreeSet<Feature>,
+}
+
+/// Renders the artifact file name (without `.wasm`) from a *template* with the placeholders
+/// `{package}`, `{version}`, `{build}` and `{features}`. Features are joined with `+`.
+///
+/// A placeholder that expands to nothing, like `{build}` of the standard build, also removes
+/// one separator (`-`, `_`, `@` or `.`) in front of it, so `{package}-{build}` gives `<package>`
+/// for the standard build.
+pub fn render(template: &str, values: &NameValues) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in name template {:?}", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = match placeholder {
+            "package" => values.package.to_string(),
+            "version" => values
+                .version
+                .ok_or_else(|| format!("Package {} has no version", values.package))?
+                .to_string(),
+            "build" => values.build.to_string(),
+            "features" => values
+                .features
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("+"),
+            _ => {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in name template {:?}",
+                    placeholder, template
+                ))
+            }
+        };
+        if value.is_empty() && name.ends_with(SEPARATORS) {
+            name.pop();
+        }
+        name.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(format!(
+            "Name template {:?} gives the invalid file name {:?}",
+            template, name
+        ));
+    }
+    Ok(name)
+}
+
+/// Checks that no two artifacts get the same file name. Takes pairs of file name and
+/// a description of the build.
+///
+/// Returns a description of every file name used more than once.
+pub fn find_duplicates(files: &[(String, String)]) -> Vec<String> {
+    let mut builds_by_file = BTreeMap::<&String, Vec<&String>>::new();
+    for (file, build) in files {
+        builds_by_file.entry(file).or_default().push(build);
+    }
+    builds_by_file
+        .into_iter()
+        .filter(|(_, builds)| builds.len() > 1)
+        .map(|(file, builds)| {
+            let builds = builds
+                .iter()
+                .map(|build| build.as_str())
+                .collect::<Vec<_>>();
+            format!("{} would be written by {}", file, builds.join(" and "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_works() {
+        let features = BTreeSet::from(["debug".to_string(), "iterator".to_string()]);
+        let debug = NameValues {
+            package: "my_contract",
+            version: Some("1.2.0"),
+            build: "debug",
+            features: &features,
+        };
+        let no_features = BTreeSet::new();
+        let standard = NameValues {
+            package: "my_contract",
+            version: Some("1.2.0"),
+            build: "",
+            features: &no_features,
+        };
+
+        assert_eq!(
+            render(DEFAULT_NAME_TEMPLATE, &debug).unwrap(),
+            "my_contract-debug"
+        );
+        assert_eq!(
+            render(DEFAULT_NAME_TEMPLATE, &standard).unwrap(),
+            "my_contract"
+        );
+        assert_eq!(
+            render("{package}-{version}-{build}", &standard).unwrap(),
+            "my_contract-1.2.0"
+        );
+        assert_eq!(
+            render("{package}@{version}", &debug).unwrap(),
+            "my_contract@1.2.0"
+        );
+        assert_eq!(
+            render("{package}_{features}", &debug).unwrap(),
+            "my_contract_debug+iterator"
+        );
+
+        let err = render("{package}-{commit}", &debug).unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown placeholder {commit} in name template \"{package}-{commit}\""
+        );
+        render("{package", &debug).unwrap_err();
+        render("{build}", &standard).unwrap_err();
+        render(
+            "{version}",
+            &NameValues {
+                version: None,
+                ..standard
+            },
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn artifact_name_works() {
+        let features = BTreeSet::new();
+        let values = |build| NameValues {
+            package: "my_contract",
+            version: Some("1.2.0"),
+            build,
+            features: &features,
+        };
+
+        assert_eq!(
+            artifact_name(Layout::Flat, None, &values("debug")).unwrap(),
+            "my_contract-debug"
+        );
+        assert_eq!(
+            artifact_name(Layout::PerPackage, None, &values("debug")).unwrap(),
+            "my_contract/debug"
+        );
+        assert_eq!(
+            artifact_name(Layout::PerPackage, None, &values("")).unwrap(),
+            "my_contract/default"
+        );
+        assert_eq!(
+            artifact_name(Layout::PerPackage, Some("{version}-{build}"), &values("")).unwrap(),
+            "my_contract/1.2.0"
+        );
+    }
+
+    #[test]
+    fn find_duplicates_works() {
+        let files = [
+            ("a.wasm".to_string(), "Standard build of a".to_string()),
+            ("b.wasm".to_string(), "Standard build of b".to_string()),
+            ("a.wasm".to_string(), "Build \"debug\" of a".to_string()),
+        ];
+        assert_eq!(
+            find_duplicates(&files),
+            vec!["a.wasm would be written by Standard build of a and Build \"debug\" of a"]
+        );
+        assert!(find_duplicates(&files[..2]).is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs, io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::json;
+
+use crate::{
+    cargo_toml::package::CosmWasmTarget,
+    checksum::sha256,
+    pkg_build::{forward_tail, BuildError, OUTPUT_TAIL_LINES},
+};
+
+const WASM_OPT_PATH: &str = "wasm-opt";
+
+/// Where the outputs of previous `wasm-opt` runs are kept
+pub const CACHE_DIR: &str = "/target/wasm-opt-cache";
+
+/// Returns the wasm-opt flags for the given target: size optimizations followed by
+/// the lowering passes of the target.
+pub fn flags(cosmwasm_target: CosmWasmTarget) -> Vec<String> {
+    let mut flags = vec!["-Os".to_string()];
+    flags.extend(
+        cosmwasm_target
+            .lowering_passes()
+            .iter()
+            .map(|pass| pass.to_string()),
+    );
+    flags
+}
+
+/// Runs wasm-opt with *flags* on the cargo output *input* and writes the optimized artifact
+/// to *output*. Fails with the end of the `wasm-opt` output if it does not succeed.
+pub fn optimize(input: &Path, output: &Path, flags: &[String]) -> Result<(), BuildError> {
+    let mut child = Command::new(WASM_OPT_PATH)
+        .args(flags)
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        // Keep stdout to bob's own messages
+        .stdout(Stdio::from(io::stderr()))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let tail = forward_tail(child.stderr.take().unwrap(), OUTPUT_TAIL_LINES);
+    let error_code = child.wait().unwrap();
+    if !error_code.success() {
+        return Err(BuildError {
+            message: format!("wasm-opt failed ({})", error_code),
+            output: tail,
+        });
+    }
+    Ok(())
+}
+
+/// Like `optimize`, but takes the output from *cache_dir* if `wasm-opt` ran on the same input
+/// with the same version and flags before. Returns true if the output was taken from the cache.
+pub fn optimize_cached(
+    input: &Path,
+    output: &Path,
+    flags: &[String],
+    cache_dir: &Path,
+) -> Result<bool, BuildError> {
+    let key = cache_key(&sha256(&fs::read(input).unwrap()), &version(), flags);
+    let cached = cache_dir.join(format!("{}.wasm", key));
+    if cached.is_file() {
+        fs::copy(&cached, output).unwrap();
+        return Ok(true);
+    }
+
+    optimize(input, output, flags)?;
+    fs::create_dir_all(cache_dir).unwrap();
+    // Write under a temporary name first so an interrupted copy is never used
+    let partial = cache_dir.join(format!("{}.wasm.partial", key));
+    fs::copy(output, &partial).unwrap();
+    fs::rename(&partial, &cached).unwrap();
+    Ok(false)
+}
+
+/// Returns the hash identifying a `wasm-opt` run on the input with hash *input_sha256*.
+fn cache_key(input_sha256: &str, version: &str, flags: &[String]) -> String {
+    let inputs = json!({
+        "input": input_sha256,
+        "wasm_opt": version,
+        "flags": flags,
+    });
+    sha256(inputs.to_string().as_bytes())
+}
+
+/// Returns the output of `wasm-opt --version`, e.g. "wasm-opt version 116 (version_116)".
+pub fn version() -> String {
+    crate::tool_version(WASM_OPT_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_works() {
+        assert_eq!(flags(CosmWasmTarget::V3), vec!["-Os"]);
+        assert_eq!(
+            flags(CosmWasmTarget::V1),
+            vec![
+                "-Os",
+                "--signext-lowering",
+                "--llvm-memory-copy-fill-lowering"
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_key_works() {
+        let version = "wasm-opt version 116 (version_116)";
+        let key = cache_key("aaaa", version, &flags(CosmWasmTarget::V3));
+        assert_eq!(key, cache_key("aaaa", version, &flags(CosmWasmTarget::V3)));
+        assert_ne!(key, cache_key("bbbb", version, &flags(CosmWasmTarget::V3)));
+        assert_ne!(key, cache_key("aaaa", version, &flags(CosmWasmTarget::V1)));
+        assert_ne!(
+            key,
+            cache_key(
+                "aaaa",
+                "wasm-opt version 123 (version_123)",
+                &flags(CosmWasmTarget::V3)
+            )
+        );
+    }
+}
@@ -1,105 +1,697 @@
-use std::{fs, path::Path, process::Command};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use serde::Deserialize;
 
-use crate::cargo_toml::package::{Build, BuildSettings};
+use crate::{
+    analysis::{analyze, validate, Analysis},
+    cache::{Cache, PackageInputs, CACHE_DIR},
+    cargo_command,
+    cargo_toml::package::{Build, BuildSettings, CosmWasmTarget},
+    checksum::sha256,
+    compress,
+    events::{BuildId, Event},
+    host_paths::HostPaths,
+    naming::{self, Layout, NameValues},
+    optimize,
+    report::{write_metadata, Artifact},
+    schema,
+    timing::{Phase, Timings},
+    toolchain::Toolchain,
+    wasm_diff::structural_diff,
+    Options,
+};
+
+/// The target directory shared by all builds
+const TARGET_DIR: &str = "/target";
+
+/// The target directory for the clean builds of the determinism check
+const DETERMINISM_TARGET_DIR: &str = "/target/determinism";
+
+/// How many lines of the cargo or tool output are kept for the report of a failed build
+pub const OUTPUT_TAIL_LINES: usize = 50;
+
+/// Why a build did not produce an artifact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildError {
+    pub message: String,
+    /// The last lines of the cargo output or other details of the failure
+    pub output: Vec<String>,
+}
+
+impl BuildError {
+    fn new(message: impl Into<String>) -> Self {
+        BuildError {
+            message: message.into(),
+            output: vec![],
+        }
+    }
+}
+
+/// A check before compiling anything that found problems, which stops the run
+#[derive(Debug)]
+pub struct CheckFailure {
+    /// Which check failed, e.g. `lockfiles`
+    pub check: &'static str,
+    /// A summary as message and the problems found as output
+    pub error: BuildError,
+}
+
+/// What became of one build of a package
+#[derive(Debug)]
+pub struct BuildResult {
+    pub package: String,
+    /// Empty for the standard build
+    pub build: String,
+    /// How long building, optimizing and analyzing took
+    pub duration: Duration,
+    pub outcome: Result<Artifact, BuildError>,
+}
+
+/// The output of a cargo build and how it was created
+#[derive(Debug, Clone)]
+pub struct CargoBuild {
+    pub wasm: PathBuf,
+    /// The arguments passed to cargo
+    pub args: Vec<String>,
+    /// The flags passed to rustc via `CARGO_ENCODED_RUSTFLAGS`
+    pub rustflags: Vec<String>,
+    /// Output of `rustc --version` of the toolchain used
+    pub rustc_version: String,
+}
 
 #[derive(Deserialize, Debug, Eq, PartialEq)]
 pub struct ParsedPackage {
     pub name: String,
+    /// `None` if the version is inherited from the workspace or not set
+    pub version: Option<String>,
     pub standard_build: bool,
     /// Builds that are created on top of the standard build
     pub builds: Vec<Build>,
+    /// Package wide default for builds without a `cosmwasm-target`
+    pub cosmwasm_target: Option<CosmWasmTarget>,
+    /// Template for the artifact file names, see `naming::render`
+    pub name_template: Option<String>,
 }
 
 impl ParsedPackage {
-    /// Build a contract with all the requested builds defined in `[package.metadata.optimizer]`
-    pub fn build(self, path: &Path) {
-        let ParsedPackage {
-            name: package_name,
-            standard_build,
-            mut builds,
-        } = self;
-        if standard_build {
+    /// Returns all builds of the package including the standard build, with the package
+    /// defaults applied.
+    pub fn all_builds(&self) -> Vec<Build> {
+        let mut builds = self.builds.clone();
+        if self.standard_build {
             builds.push(Build::default()); // add contract with default features
         }
+        for build in builds.iter_mut() {
+            build.settings.cosmwasm_target =
+                build.settings.cosmwasm_target.or(self.cosmwasm_target);
+        }
+        builds
+    }
+
+    /// Returns the path of the artifact of *build* relative to the artifacts directory.
+    pub fn artifact_file(&self, build: &Build, layout: Layout) -> Result<String, String> {
+        let name = naming::artifact_name(
+            layout,
+            self.name_template.as_deref(),
+            &NameValues {
+                package: &self.name,
+                version: self.version.as_deref(),
+                build: &build.name,
+                features: &build.settings.features.clone().unwrap_or_default(),
+            },
+        )?;
+        Ok(format!("{}.wasm", name))
+    }
+
+    /// Build a contract with all the requested builds defined in `[package.metadata.optimizer]`
+    /// and store the optimized artifacts in the artifacts directory. A failed build does not
+    /// stop the other builds. The durations of the phases are added to *timings*.
+    pub fn build(
+        self,
+        path: &Path,
+        options: &Options,
+        toolchain: &Toolchain,
+        host_paths: &HostPaths,
+        timings: &mut Timings,
+    ) -> Vec<BuildResult> {
+        let package_name = &self.name;
+        let builds = self
+            .all_builds()
+            .into_iter()
+            .map(|build| {
+                let file = self.artifact_file(&build, options.layout).unwrap();
+                (build, file)
+            })
+            .collect::<Vec<_>>();
+
+        // Reuse artifacts whose inputs did not change since a previous run.
+        // The determinism check needs fresh builds.
+        let cache = (!options.no_cache && !options.check_determinism).then(|| {
+            let inputs = timings.measure(Some(package_name), None, Phase::CacheInputs, || {
+                PackageInputs::collect(path, options, toolchain)
+            });
+            (Cache::new(CACHE_DIR), inputs)
+        });
 
         // Build all the requested builds
-        for build in builds.into_iter() {
-            build.build(path, &package_name);
+        let mut results = builds
+            .iter()
+            .map(|(build, file)| {
+                let start = Instant::now();
+                let outcome = build.create_artifact(
+                    path,
+                    package_name,
+                    file,
+                    cache.as_ref(),
+                    options,
+                    toolchain,
+                    host_paths,
+                    timings,
+                );
+                options.emit(Event::BuildFinished {
+                    id: build.id(package_name),
+                    error: outcome.as_ref().err().map(|err| err.message.clone()),
+                });
+                BuildResult {
+                    package: package_name.clone(),
+                    build: build.name.clone(),
+                    duration: start.elapsed(),
+                    outcome,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // All builds of the package share the schema, which comes from the same sources
+        let schema = if options.schema {
+            timings.measure(Some(package_name), None, Phase::Schema, || {
+                schema::generate(path, package_name, options, toolchain)
+            })
+        } else {
+            Ok(None)
+        };
+        let schema = match schema {
+            Ok(schema) => schema.unwrap_or_default(),
+            Err(err) => {
+                options.emit(Event::Error {
+                    id: None,
+                    message: err.message.clone(),
+                });
+                // Without their schema the artifacts are incomplete
+                for result in results.iter_mut().filter(|result| result.outcome.is_ok()) {
+                    result.outcome = Err(err.clone());
+                }
+                BTreeMap::new()
+            }
+        };
+        let mut artifacts = results
+            .iter_mut()
+            .filter_map(|result| result.outcome.as_mut().ok())
+            .collect::<Vec<_>>();
+
+        // Point out build variants that lost entry points the other variants have,
+        // e.g. because of `default-features = false`
+        let all_entry_points = artifacts
+            .iter()
+            .flat_map(|artifact| artifact.entry_points.iter())
+            .collect::<BTreeSet<_>>();
+        for artifact in &artifacts {
+            let missing = all_entry_points
+                .iter()
+                .filter(|entry_point| !artifact.entry_points.contains(**entry_point))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                options.emit(Event::Note {
+                    id: Some(BuildId {
+                        package: artifact.package.clone(),
+                        build: artifact.build.clone(),
+                    }),
+                    message: format!(
+                        "{} does not export {:?}, which other builds of {} export",
+                        artifact.file, missing, package_name
+                    ),
+                });
+            }
         }
+
+        for artifact in artifacts.iter_mut() {
+            if options.gzip {
+                let gzip_sha256 = timings.measure(
+                    Some(&artifact.package),
+                    Some(&artifact.build),
+                    Phase::Compress,
+                    || compress::write_gzip(&options.artifacts_dir, &artifact.file),
+                );
+                artifact.gzip_sha256 = Some(gzip_sha256);
+            }
+            artifact.schema = schema.clone();
+            write_metadata(&options.artifacts_dir, artifact);
+        }
+
+        results
     }
 }
 
 impl Build {
-    /// Build the contract at the path *contract*.
-    pub fn build(self, contract: &Path, package_name: &str) {
+    /// Builds and optimizes the artifact *file*, or copies it from the *cache* if its inputs
+    /// did not change. The durations of compiling and optimizing are added to *timings*.
+    #[allow(clippy::too_many_arguments)]
+    fn create_artifact(
+        &self,
+        path: &Path,
+        package_name: &str,
+        file: &str,
+        cache: Option<&(Cache, PackageInputs)>,
+        options: &Options,
+        toolchain: &Toolchain,
+        host_paths: &HostPaths,
+        timings: &mut Timings,
+    ) -> Result<Artifact, BuildError> {
+        let cosmwasm_target = self.settings.cosmwasm_target.unwrap_or_default();
+        let key = cache.map(|(_, inputs)| {
+            inputs.key(
+                self,
+                file,
+                &rustflags(host_paths),
+                &optimize::flags(cosmwasm_target),
+            )
+        });
+        if let (Some((cache, _)), Some(key)) = (cache, &key) {
+            if let Some(artifact) = cache.load(key, &options.artifacts_dir) {
+                options.emit(Event::CacheHit {
+                    id: self.id(package_name),
+                    file: artifact.file.clone(),
+                });
+                return Ok(artifact);
+            }
+        }
+
+        let cargo_build =
+            timings.measure(Some(package_name), Some(&self.name), Phase::Compile, || {
+                if options.check_determinism {
+                    self.build_twice(path, package_name, options, toolchain, host_paths)
+                } else {
+                    self.build(
+                        path,
+                        package_name,
+                        Path::new(TARGET_DIR),
+                        options,
+                        toolchain,
+                        host_paths,
+                    )
+                }
+            })?;
+        let artifact = timings.measure(
+            Some(package_name),
+            Some(&self.name),
+            Phase::Optimize,
+            || self.optimize(&cargo_build, package_name, file, options, host_paths),
+        )?;
+        if let (Some((cache, _)), Some(key)) = (cache, &key) {
+            cache.store(key, &artifact, &options.artifacts_dir);
+        }
+        Ok(artifact)
+    }
+
+    /// Build the contract at the path *contract* into *target_dir*.
+    pub fn build(
+        &self,
+        contract: &Path,
+        package_name: &str,
+        target_dir: &Path,
+        options: &Options,
+        toolchain: &Toolchain,
+        host_paths: &HostPaths,
+    ) -> Result<CargoBuild, BuildError> {
         let Build {
             name: build_name,
             settings:
                 BuildSettings {
                     features,
                     default_features,
+                    cosmwasm_target: _,
                 },
         } = self;
 
-        let features = features.unwrap_or_default();
+        let features = features.clone().unwrap_or_default();
 
-        eprintln!("Building {} with features {:?}", package_name, features);
+        options.emit(Event::BuildStarted {
+            id: self.id(package_name),
+            features: features.clone(),
+        });
 
         let mut args = vec![
             "build",
             "--release",
             "--lib",
-            "--target-dir=/target",
             "--target=wasm32-unknown-unknown",
             "--locked",
         ]
         .into_iter()
         .map(|arg| arg.to_string())
         .collect::<Vec<String>>();
+        args.push(format!("--target-dir={}", target_dir.display()));
+        if options.offline {
+            args.push("--offline".to_string());
+        }
 
         // Add features to command
         let features_arg = features.into_iter().collect::<Vec<String>>().join(", ");
         args.push(format!("--features={}", features_arg));
 
         // add default features to command
-        if let Some(false) = default_features {
+        if let Some(false) = *default_features {
             args.push("--no-default-features".to_string());
         }
 
-        // Run the build
-        let mut child = Command::new(crate::CARGO_PATH)
+        let rustflags = rustflags(host_paths);
+
+        // Run the build, keeping the end of its output for the report
+        let mut child = cargo_command(options, toolchain)
             .args(&args)
-            .env("RUSTFLAGS", "-C link-arg=-s")
+            // Use the encoded form as paths might contain spaces
+            .env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"))
             .current_dir(fs::canonicalize(contract).unwrap())
+            // Keep stdout to bob's own messages
+            .stdout(Stdio::from(io::stderr()))
+            .stderr(Stdio::piped())
             .spawn()
             .unwrap();
+        let output = forward_tail(child.stderr.take().unwrap(), OUTPUT_TAIL_LINES);
         let error_code = child.wait().unwrap();
-        assert!(error_code.success());
+        if !error_code.success() {
+            return Err(BuildError {
+                message: format!("cargo build failed ({})", error_code),
+                output,
+            });
+        }
 
         // Rename to name formatted as `<output_dir>/<wasm_name>-<build_name>.wasm`
         if !build_name.is_empty() {
-            let input_wasm_path = default_wasm_path(package_name);
-            let output_wasm_path = wasm_path(package_name, &build_name);
+            let input_wasm_path = default_wasm_path(target_dir, package_name);
+            let output_wasm_path = wasm_path(target_dir, package_name, build_name);
             fs::rename(&input_wasm_path, &output_wasm_path)
                 .expect("Failed to rename the output file");
         }
+
+        Ok(CargoBuild {
+            wasm: wasm_path(target_dir, package_name, build_name),
+            args,
+            rustflags,
+            rustc_version: toolchain.rustc_version.clone(),
+        })
     }
+
+    /// Build the contract twice, each time from a clean target directory, and ensure both
+    /// results are byte for byte identical.
+    pub fn build_twice(
+        &self,
+        contract: &Path,
+        package_name: &str,
+        options: &Options,
+        toolchain: &Toolchain,
+        host_paths: &HostPaths,
+    ) -> Result<CargoBuild, BuildError> {
+        let target_dir = Path::new(DETERMINISM_TARGET_DIR);
+        let mut results = vec![];
+        for _ in 0..2 {
+            if target_dir.exists() {
+                fs::remove_dir_all(target_dir).unwrap();
+            }
+            let cargo_build = self.build(
+                contract,
+                package_name,
+                target_dir,
+                options,
+                toolchain,
+                host_paths,
+            )?;
+            results.push((fs::read(&cargo_build.wasm).unwrap(), cargo_build));
+        }
+
+        let (first, _) = &results[0];
+        let (second, cargo_build) = &results[1];
+        let differences = if first == second {
+            vec![]
+        } else {
+            structural_diff(first, second)
+                .expect("Failed to parse cargo output")
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        options.emit(Event::DeterminismChecked {
+            id: self.id(package_name),
+            differences: differences.clone(),
+        });
+        if first != second {
+            return Err(BuildError {
+                message: "Non-deterministic build".to_string(),
+                output: differences,
+            });
+        }
+        Ok(cargo_build.clone())
+    }
+
+    /// Returns the identifier of the build in events.
+    pub fn id(&self, package_name: &str) -> BuildId {
+        BuildId {
+            package: package_name.to_string(),
+            build: self.name.clone(),
+        }
+    }
+
+    /// Returns a human readable description of the build including its features,
+    /// e.g. for error messages.
+    pub fn describe(&self, package_name: &str) -> String {
+        let name = if self.name.is_empty() {
+            "Standard build".to_string()
+        } else {
+            format!("Build {:?}", self.name)
+        };
+        format!(
+            "{} of {} (features: {:?}, default-features: {})",
+            name,
+            package_name,
+            self.settings.features.clone().unwrap_or_default(),
+            self.settings.default_features.unwrap_or(true)
+        )
+    }
+
+    /// Validates the optimized *bytecode* of *file* against the CosmWasm target of the build and
+    /// checks its entry points. Errors name the build and its features.
+    fn check(
+        &self,
+        package_name: &str,
+        file: &str,
+        bytecode: &[u8],
+    ) -> Result<Analysis, BuildError> {
+        let cosmwasm_target = self.settings.cosmwasm_target.unwrap_or_default();
+        if let Err(err) = validate(bytecode, cosmwasm_target.wasm_features()) {
+            return Err(BuildError::new(format!(
+                "{}: Artifact {} is not compatible with CosmWasm {}: {}",
+                self.describe(package_name),
+                file,
+                cosmwasm_target,
+                err
+            )));
+        }
+        let analysis = analyze(bytecode).expect("Failed to parse artifact");
+        if let Err(err) = analysis.check_ibc_entry_points() {
+            return Err(BuildError::new(format!(
+                "{}: {}",
+                self.describe(package_name),
+                err
+            )));
+        }
+        Ok(analysis)
+    }
+
+    /// Optimize the cargo output into *file* in the artifacts directory and record what the
+    /// artifact contains.
+    pub fn optimize(
+        &self,
+        cargo_build: &CargoBuild,
+        package_name: &str,
+        file: &str,
+        options: &Options,
+        host_paths: &HostPaths,
+    ) -> Result<Artifact, BuildError> {
+        let wasm = &cargo_build.wasm;
+        let file = file.to_string();
+        let output = options.artifacts_dir.join(&file);
+        fs::create_dir_all(output.parent().unwrap()).unwrap();
+        let cosmwasm_target = self.settings.cosmwasm_target.unwrap_or_default();
+        let wasm_opt_flags = optimize::flags(cosmwasm_target);
+
+        let intermediate = fs::read(wasm).unwrap();
+        options.emit(Event::OptimizeStarted {
+            id: self.id(package_name),
+            file: file.clone(),
+            cosmwasm_target: cosmwasm_target.to_string(),
+        });
+        let cached = if options.no_cache {
+            optimize::optimize(wasm, &output, &wasm_opt_flags)?;
+            false
+        } else {
+            optimize::optimize_cached(
+                wasm,
+                &output,
+                &wasm_opt_flags,
+                Path::new(optimize::CACHE_DIR),
+            )?
+        };
+        options.emit(Event::OptimizeFinished {
+            id: self.id(package_name),
+            file: file.clone(),
+            cached,
+        });
+
+        let bytecode = fs::read(&output).unwrap();
+        let analysis = self.check(package_name, &file, &bytecode)?;
+        let absolute_paths = host_paths.find_in(&bytecode);
+        if !absolute_paths.is_empty() {
+            options.emit(Event::Warning {
+                id: Some(self.id(package_name)),
+                message: format!(
+                    "{} contains absolute host paths, so its bytes depend on the build machine: {:?}",
+                    file, absolute_paths
+                ),
+            });
+        }
+        options.emit(Event::Validated {
+            id: self.id(package_name),
+            file: file.clone(),
+            entry_points: analysis.entry_points.clone(),
+            required_capabilities: analysis.required_capabilities.clone(),
+        });
+
+        Ok(Artifact {
+            file,
+            package: package_name.to_string(),
+            build: self.name.clone(),
+            features: self.settings.features.clone().unwrap_or_default(),
+            default_features: self.settings.default_features.unwrap_or(true),
+            cosmwasm_target,
+            required_capabilities: analysis.required_capabilities,
+            entry_points: analysis.entry_points,
+            absolute_paths,
+            sha256: sha256(&bytecode),
+            size: bytecode.len() as u64,
+            gzip_sha256: None,
+            intermediate_sha256: sha256(&intermediate),
+            intermediate_size: intermediate.len() as u64,
+            intermediate_path: Some(wasm.clone()),
+            cargo_args: cargo_build.args.clone(),
+            rustflags: cargo_build.rustflags.clone(),
+            rustc_version: cargo_build.rustc_version.clone(),
+            wasm_opt_flags,
+            schema: BTreeMap::new(),
+            cache_hit: false,
+        })
+    }
+}
+
+/// Copies the lines of *output* to stderr and returns the last *count* of them.
+pub fn forward_tail(output: impl Read, count: usize) -> Vec<String> {
+    let mut tail = VecDeque::with_capacity(count);
+    let mut reader = BufReader::new(output);
+    let mut line = vec![];
+    while reader.read_until(b'\n', &mut line).unwrap() > 0 {
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        eprintln!("{}", text);
+        if tail.len() == count {
+            tail.pop_front();
+        }
+        tail.push_back(text.to_string());
+        line.clear();
+    }
+    tail.into()
+}
+
+/// Returns the flags passed to rustc, which strip the binary and make sure none of the
+/// *host_paths* end up in it
+fn rustflags(host_paths: &HostPaths) -> Vec<String> {
+    let mut rustflags = vec!["-C".to_string(), "link-arg=-s".to_string()];
+    rustflags.extend(host_paths.remap_flags());
+    rustflags
+}
+
+/// Returns the path of the cargo output of a build in the shared target directory
+pub fn target_wasm_path(package_name: &str, build_name: &str) -> PathBuf {
+    wasm_path(Path::new(TARGET_DIR), package_name, build_name)
 }
 
-pub const OUTPUT_DIR: &str = "/target/wasm32-unknown-unknown/release";
+/// Returns the directory cargo writes the Wasm files to
+fn output_dir(target_dir: &Path) -> PathBuf {
+    target_dir.join("wasm32-unknown-unknown/release")
+}
 
 /// Returns the default wasm path name formatted as `<output_dir>/<wasm_name>.wasm`
-fn default_wasm_path(wasm_name: &str) -> String {
-    format!("{}/{}.wasm", OUTPUT_DIR, wasm_name)
+fn default_wasm_path(target_dir: &Path, wasm_name: &str) -> PathBuf {
+    output_dir(target_dir).join(format!("{}.wasm", wasm_name))
 }
 
 /// Returns path name formatted as `<output_dir>/<wasm_name>-<build_name>.wasm`
-fn wasm_path(wasm_name: &str, build_name: &str) -> String {
+fn wasm_path(target_dir: &Path, wasm_name: &str, build_name: &str) -> PathBuf {
     if build_name.is_empty() {
-        default_wasm_path(wasm_name)
+        default_wasm_path(target_dir, wasm_name)
     } else {
-        format!("{}/{}-{}.wasm", OUTPUT_DIR, wasm_name, build_name)
+        output_dir(target_dir).join(format!("{}-{}.wasm", wasm_name, build_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_tail_works() {
+        let output = "Compiling a\nCompiling b\r\nerror: failed\n";
+        assert_eq!(
+            forward_tail(output.as_bytes(), 2),
+            vec!["Compiling b", "error: failed"]
+        );
+        assert_eq!(forward_tail("no newline".as_bytes(), 2), vec!["no newline"]);
+        assert!(forward_tail(&b""[..], 2).is_empty());
+    }
+
+    #[test]
+    fn check_names_build_and_features() {
+        let build = Build {
+            name: "ibc".to_string(),
+            settings: BuildSettings {
+                features: Some(BTreeSet::from(["ibc".to_string()])),
+                default_features: Some(false),
+                cosmwasm_target: Some(CosmWasmTarget::V1),
+            },
+        };
+
+        let wasm = wat::parse_str(r#"(module (func (export "ibc_channel_open")))"#).unwrap();
+        let err = build
+            .check("my_contract", "my_contract-ibc.wasm", &wasm)
+            .unwrap_err();
+        assert!(err.message.starts_with(
+            r#"Build "ibc" of my_contract (features: {"ibc"}, default-features: false): "#
+        ));
+
+        let wasm = wat::parse_str(
+            r#"(module (func (export "extend") (param i32) (result i32) local.get 0 i32.extend8_s))"#,
+        )
+        .unwrap();
+        let err = build
+            .check("my_contract", "my_contract-ibc.wasm", &wasm)
+            .unwrap_err();
+        assert!(err
+            .message
+            .contains(r#"(features: {"ibc"}, default-features: false)"#));
+        assert!(err.message.contains("not compatible with CosmWasm 1.x"));
     }
 }
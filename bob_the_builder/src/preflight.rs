@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use crate::{cargo_command, toolchain::Toolchain, Options};
+
+/// Checks that the project root has a `Cargo.lock` and that it is up to date for the
+/// root and each of the *packages*, such that the `--locked` builds cannot fail because
+/// of the lockfile after some contracts were already built.
+///
+/// In offline mode this also ensures all dependencies are in the registry cache.
+///
+/// Returns a description of every problem found.
+pub fn check_lockfiles(
+    root: &Path,
+    packages: &[&Path],
+    options: &Options,
+    toolchain: &Toolchain,
+) -> Vec<String> {
+    if !root.join("Cargo.lock").is_file() {
+        return vec![format!(
+            "No Cargo.lock found in {}. Run `cargo generate-lockfile` and commit the result.",
+            root.display()
+        )];
+    }
+
+    let mut dirs = vec![root];
+    dirs.extend(packages.iter().filter(|dir| **dir != root));
+    dirs.into_iter()
+        .filter_map(|dir| check_lockfile(dir, options, toolchain).err())
+        .collect()
+}
+
+/// Lets cargo resolve the dependencies of the package in *dir* without updating the lockfile.
+fn check_lockfile(dir: &Path, options: &Options, toolchain: &Toolchain) -> Result<(), String> {
+    let output = cargo_command(options, toolchain)
+        .args(["metadata", "--locked", "--format-version=1"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let problem = if options.offline {
+        "Cargo.lock is not up to date or dependencies are missing in the registry cache (see `bob fetch`)"
+    } else {
+        "Cargo.lock is not up to date"
+    };
+    Err(format!(
+        "{} for {}:\n{}",
+        problem,
+        dir.display(),
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// Creates a package without `Cargo.lock` in a new temporary directory.
+    fn package(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("bob-{}-{}", name, std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"preflight\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn check_lockfiles_reports_missing_lockfile() {
+        let root = package("preflight-missing");
+        // Never used, as the check stops before running cargo
+        let toolchain = Toolchain {
+            name: "1.86.0".to_string(),
+            rustc_version: "rustc 1.86.0 (05f9846f8 2025-03-31)".to_string(),
+        };
+
+        let problems = check_lockfiles(&root, &[], &Options::default(), &toolchain);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("No Cargo.lock found"),
+            "{:?}",
+            problems
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[ignore = "runs cargo metadata with the active rustup toolchain"]
+    fn check_lockfiles_works() {
+        let root = package("preflight");
+
+        // Up to date
+        fs::write(
+            root.join("Cargo.lock"),
+            "version = 3\n\n[[package]]\nname = \"preflight\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_lockfiles(&root, &[&root], &Options::default(), &Toolchain::active()),
+            Vec::<String>::new()
+        );
+
+        // Outdated
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"preflight\"\nversion = \"0.2.0\"\n",
+        )
+        .unwrap();
+        let problems = check_lockfiles(&root, &[&root], &Options::default(), &Toolchain::active());
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("Cargo.lock is not up to date"),
+            "{:?}",
+            problems
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
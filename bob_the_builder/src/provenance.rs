@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    cargo_toml::package::{BuildName, CosmWasmTarget, Feature},
+    checksum::sha256,
+    report::Artifact,
+};
+
+/// File name of the provenance document in the artifacts directory
+pub const PROVENANCE_FILE: &str = "provenance.intoto.json";
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+const BUILD_TYPE: &str = "https://github.com/CosmWasm/optimizer/bob@v1";
+const BUILDER_ID: &str = "https://github.com/CosmWasm/optimizer";
+
+/// Provenance of the artifacts as an [in-toto statement] with a [SLSA provenance] predicate.
+/// It is generated locally from what bob knows about the build and is not signed.
+///
+/// [in-toto statement]: https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md
+/// [SLSA provenance]: https://slsa.dev/spec/v1.0/provenance
+#[derive(Serialize, Debug)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    statement_type: &'static str,
+    subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    predicate_type: &'static str,
+    predicate: Predicate,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct ResourceDescriptor {
+    name: String,
+    digest: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Predicate {
+    build_definition: BuildDefinition,
+    run_details: RunDetails,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BuildDefinition {
+    build_type: &'static str,
+    /// The resolved `[package.metadata.optimizer]` settings of each build
+    external_parameters: BTreeMap<String, ExternalParameters>,
+    /// How bob invoked the tools for each build
+    internal_parameters: BTreeMap<String, InternalParameters>,
+    resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Serialize, Debug)]
+struct ExternalParameters {
+    package: String,
+    build: BuildName,
+    features: Vec<Feature>,
+    default_features: bool,
+    cosmwasm_target: CosmWasmTarget,
+}
+
+#[derive(Serialize, Debug)]
+struct InternalParameters {
+    cargo_args: Vec<String>,
+    rustflags: Vec<String>,
+    rustc_version: String,
+    wasm_opt_flags: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct RunDetails {
+    builder: Builder,
+}
+
+#[derive(Serialize, Debug)]
+struct Builder {
+    id: &'static str,
+    /// Versions of the tools used, e.g. `rustc` and `wasm-opt`
+    version: BTreeMap<&'static str, String>,
+}
+
+fn sha256_digest(data: &[u8]) -> BTreeMap<&'static str, String> {
+    BTreeMap::from([("sha256", sha256(data))])
+}
+
+impl Statement {
+    /// Creates the statement for the artifacts of a run. *cargo_lock* is the content of the
+    /// lockfile the artifacts were built with.
+    pub fn new(
+        artifacts: &[Artifact],
+        cargo_lock: &[u8],
+        rustc_version: String,
+        wasm_opt_version: String,
+    ) -> Self {
+        let subject = artifacts
+            .iter()
+            .map(|artifact| ResourceDescriptor {
+                name: artifact.file.clone(),
+                digest: BTreeMap::from([("sha256", artifact.sha256.clone())]),
+            })
+            .collect();
+        let external_parameters = artifacts
+            .iter()
+            .map(|artifact| {
+                let parameters = ExternalParameters {
+                    package: artifact.package.clone(),
+                    build: artifact.build.clone(),
+                    features: artifact.features.iter().cloned().collect(),
+                    default_features: artifact.default_features,
+                    cosmwasm_target: artifact.cosmwasm_target,
+                };
+                (artifact.file.clone(), parameters)
+            })
+            .collect();
+        let internal_parameters = artifacts
+            .iter()
+            .map(|artifact| {
+                let parameters = InternalParameters {
+                    cargo_args: artifact.cargo_args.clone(),
+                    rustflags: artifact.rustflags.clone(),
+                    rustc_version: artifact.rustc_version.clone(),
+                    wasm_opt_flags: artifact.wasm_opt_flags.clone(),
+                };
+                (artifact.file.clone(), parameters)
+            })
+            .collect();
+
+        Statement {
+            statement_type: STATEMENT_TYPE,
+            subject,
+            predicate_type: PREDICATE_TYPE,
+            predicate: Predicate {
+                build_definition: BuildDefinition {
+                    build_type: BUILD_TYPE,
+                    external_parameters,
+                    internal_parameters,
+                    resolved_dependencies: vec![ResourceDescriptor {
+                        name: "Cargo.lock".to_string(),
+                        digest: sha256_digest(cargo_lock),
+                    }],
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: BUILDER_ID,
+                        version: BTreeMap::from([
+                            ("rustc", rustc_version),
+                            ("wasm-opt", wasm_opt_version),
+                        ]),
+                    },
+                },
+            },
+        }
+    }
+}
+
+/// Writes the provenance document to the artifacts directory.
+pub fn write_provenance(artifacts_dir: &Path, statement: &Statement) {
+    let json = serde_json::to_string_pretty(statement).unwrap();
+    fs::write(artifacts_dir.join(PROVENANCE_FILE), json + "\n")
+        .expect("Failed to write provenance");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use super::*;
+
+    use crate::report::test_artifact;
+
+    #[test]
+    fn statement_works() {
+        let artifact = Artifact {
+            features: BTreeSet::from(["debug".to_string()]),
+            sha256: "aaaa".to_string(),
+            intermediate_sha256: "bbbb".to_string(),
+            cargo_args: vec!["build".to_string(), "--features=debug".to_string()],
+            rustflags: vec!["-C".to_string(), "link-arg=-s".to_string()],
+            rustc_version: "rustc 1.81.0 (eeb90cda1 2024-09-04)".to_string(),
+            wasm_opt_flags: vec!["-Os".to_string()],
+            ..test_artifact("my_contract", "debug")
+        };
+
+        let statement = Statement::new(
+            &[artifact],
+            b"",
+            "rustc 1.86.0 (05f9846f8 2025-03-31)".to_string(),
+            "wasm-opt version 116 (version_116)".to_string(),
+        );
+        assert_eq!(
+            serde_json::to_value(&statement).unwrap(),
+            json!({
+                "_type": "https://in-toto.io/Statement/v1",
+                "subject": [
+                    { "name": "my_contract-debug.wasm", "digest": { "sha256": "aaaa" } }
+                ],
+                "predicateType": "https://slsa.dev/provenance/v1",
+                "predicate": {
+                    "buildDefinition": {
+                        "buildType": "https://github.com/CosmWasm/optimizer/bob@v1",
+                        "externalParameters": {
+                            "my_contract-debug.wasm": {
+                                "package": "my_contract",
+                                "build": "debug",
+                                "features": ["debug"],
+                                "default_features": true,
+                                "cosmwasm_target": "3.x"
+                            }
+                        },
+                        "internalParameters": {
+                            "my_contract-debug.wasm": {
+                                "cargo_args": ["build", "--features=debug"],
+                                "rustflags": ["-C", "link-arg=-s"],
+                                "rustc_version": "rustc 1.81.0 (eeb90cda1 2024-09-04)",
+                                "wasm_opt_flags": ["-Os"]
+                            }
+                        },
+                        "resolvedDependencies": [
+                            {
+                                "name": "Cargo.lock",
+                                "digest": {
+                                    "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                                }
+                            }
+                        ]
+                    },
+                    "runDetails": {
+                        "builder": {
+                            "id": "https://github.com/CosmWasm/optimizer",
+                            "version": {
+                                "rustc": "rustc 1.86.0 (05f9846f8 2025-03-31)",
+                                "wasm-opt": "wasm-opt version 116 (version_116)"
+                            }
+                        }
+                    }
+                }
+            })
+        );
+    }
+}
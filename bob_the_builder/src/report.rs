@@ -0,0 +1,371 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cargo_toml::package::{BuildName, CosmWasmTarget, Feature},
+    checksum::format_checksums,
+    compress::GZIP_SUFFIX,
+    timing::TimingReport,
+};
+
+/// File name of the build report in the artifacts directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// File name of the package index of the per-package layout in the artifacts directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// File name of the checksums in the artifacts directory and in each package directory
+pub const CHECKSUMS_FILE: &str = "checksums.txt";
+
+/// File name of the checksums of the cargo outputs in the artifacts directory
+pub const INTERMEDIATE_CHECKSUMS_FILE: &str = "checksums_intermediate.txt";
+
+/// Everything we know about one optimized artifact.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Artifact {
+    /// Path of the artifact relative to the artifacts directory,
+    /// e.g. `my_contract.wasm` or `my_contract/debug.wasm`
+    pub file: String,
+    pub package: String,
+    /// Name of the build. Empty for the standard build.
+    pub build: BuildName,
+    pub features: BTreeSet<Feature>,
+    pub default_features: bool,
+    /// The CosmWasm version range the artifact was optimized and validated for
+    pub cosmwasm_target: CosmWasmTarget,
+    /// Capabilities signaled by `requires_*` exports
+    pub required_capabilities: BTreeSet<String>,
+    /// Exported entry points like `instantiate`, `migrate` or `ibc_channel_open`
+    pub entry_points: BTreeSet<String>,
+    /// Absolute paths of the build host found in the artifact
+    pub absolute_paths: BTreeSet<String>,
+    /// SHA-256 hash of the artifact
+    pub sha256: String,
+    /// Size of the artifact in bytes
+    #[serde(default)]
+    pub size: u64,
+    /// SHA-256 hash of the compressed artifact `<file>.gz` if one was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gzip_sha256: Option<String>,
+    /// SHA-256 hash of the cargo output before `wasm-opt`
+    pub intermediate_sha256: String,
+    /// Size of the cargo output before `wasm-opt` in bytes
+    #[serde(default)]
+    pub intermediate_size: u64,
+    /// The cargo output the artifact was optimized from, unless it was taken from the build cache
+    #[serde(skip)]
+    pub intermediate_path: Option<PathBuf>,
+    /// The arguments passed to cargo
+    pub cargo_args: Vec<String>,
+    /// The flags passed to rustc
+    pub rustflags: Vec<String>,
+    /// Output of `rustc --version` of the toolchain the artifact was compiled with
+    pub rustc_version: String,
+    /// The flags passed to `wasm-opt`
+    pub wasm_opt_flags: Vec<String>,
+    /// SHA-256 hashes of the JSON schema files of the package by their path relative to the
+    /// artifacts directory. Empty if no schema was generated.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub schema: BTreeMap<String, String>,
+    /// True if the artifact was taken from the build cache because its inputs did not change.
+    /// Only reported as an event, so cold and warm runs write the same files.
+    #[serde(skip)]
+    pub cache_hit: bool,
+}
+
+impl Artifact {
+    /// Returns the file name of the metadata file, formatted as `<artifact_name>.metadata.json`
+    pub fn metadata_file(&self) -> String {
+        let stem = self.file.strip_suffix(".wasm").unwrap_or(&self.file);
+        format!("{}.metadata.json", stem)
+    }
+
+    /// Returns the files of this artifact with their SHA-256 hashes: the Wasm and the
+    /// compressed Wasm if there is one.
+    pub fn checksums(&self) -> Vec<(String, &str)> {
+        let mut checksums = vec![(self.file.clone(), self.sha256.as_str())];
+        if let Some(gzip_sha256) = &self.gzip_sha256 {
+            checksums.push((format!("{}{}", self.file, GZIP_SUFFIX), gzip_sha256));
+        }
+        checksums
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Manifest<'a> {
+    artifacts: &'a [Artifact],
+    /// Differs between runs of the same build, so bundles leave it out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<TimingReport<'a>>,
+}
+
+/// The part of a manifest that only depends on the artifacts
+#[derive(Deserialize, Debug)]
+struct ReproducibleManifest {
+    artifacts: Vec<Artifact>,
+}
+
+fn manifest_json(artifacts: &[Artifact], timings: Option<TimingReport>) -> String {
+    serde_json::to_string_pretty(&Manifest { artifacts, timings }).unwrap() + "\n"
+}
+
+/// Writes the metadata file of a single artifact next to the artifact.
+pub fn write_metadata(artifacts_dir: &Path, artifact: &Artifact) {
+    let json = serde_json::to_string_pretty(artifact).unwrap();
+    fs::write(artifacts_dir.join(artifact.metadata_file()), json + "\n")
+        .expect("Failed to write artifact metadata");
+}
+
+/// Writes the build report covering all artifacts of this run and how long its phases took.
+pub fn write_manifest(artifacts_dir: &Path, artifacts: &[Artifact], timings: TimingReport) {
+    fs::write(
+        artifacts_dir.join(MANIFEST_FILE),
+        manifest_json(artifacts, Some(timings)),
+    )
+    .expect("Failed to write manifest");
+}
+
+/// Returns the *manifest* without its timings, so that it only depends on the artifacts.
+/// Returns `None` if *manifest* is not a manifest written by bob.
+pub fn without_timings(manifest: &[u8]) -> Option<String> {
+    let manifest = serde_json::from_slice::<ReproducibleManifest>(manifest).ok()?;
+    Some(manifest_json(&manifest.artifacts, None))
+}
+
+/// Writes the checksums of all artifacts to `checksums.txt` and, if *intermediate* is set,
+/// the checksums of the cargo outputs they were optimized from to `checksums_intermediate.txt`.
+/// Both are sorted by file name and can be checked with `sha256sum -c`. The latter lists the
+/// cargo outputs under the paths they were compiled to, e.g.
+/// `/target/wasm32-unknown-unknown/release/my_contract.wasm`, like older optimizer versions did,
+/// and leaves out cached artifacts, which were not compiled. Without *intermediate*, a
+/// `checksums_intermediate.txt` of a previous run is removed.
+pub fn write_checksums(artifacts_dir: &Path, artifacts: &[Artifact], intermediate: bool) {
+    let files = artifacts
+        .iter()
+        .flat_map(Artifact::checksums)
+        .collect::<Vec<_>>();
+    let checksums = format_checksums(files.iter().map(|(file, sha256)| (file.as_str(), *sha256)));
+    fs::write(artifacts_dir.join(CHECKSUMS_FILE), checksums).expect("Failed to write checksums");
+
+    let intermediate_checksums = artifacts_dir.join(INTERMEDIATE_CHECKSUMS_FILE);
+    if intermediate {
+        let files = artifacts
+            .iter()
+            .filter_map(|artifact| {
+                let path = artifact.intermediate_path.as_ref()?;
+                Some((
+                    path.display().to_string(),
+                    artifact.intermediate_sha256.as_str(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let checksums =
+            format_checksums(files.iter().map(|(file, sha256)| (file.as_str(), *sha256)));
+        fs::write(intermediate_checksums, checksums)
+            .expect("Failed to write intermediate checksums");
+    } else if intermediate_checksums.is_file() {
+        fs::remove_file(intermediate_checksums).unwrap();
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Index<'a> {
+    packages: BTreeMap<&'a str, PackageIndex<'a>>,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+struct PackageIndex<'a> {
+    /// Path of the package's checksums file relative to the artifacts directory
+    checksums: String,
+    artifacts: Vec<IndexEntry<'a>>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct IndexEntry<'a> {
+    build: &'a str,
+    file: &'a str,
+    sha256: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gzip_sha256: Option<&'a str>,
+}
+
+fn index(artifacts: &[Artifact]) -> Index<'_> {
+    let mut packages = BTreeMap::<_, PackageIndex>::new();
+    for artifact in artifacts {
+        let package = packages.entry(artifact.package.as_str()).or_default();
+        package.checksums = format!("{}/{}", artifact.package, CHECKSUMS_FILE);
+        package.artifacts.push(IndexEntry {
+            build: &artifact.build,
+            file: &artifact.file,
+            sha256: &artifact.sha256,
+            gzip_sha256: artifact.gzip_sha256.as_deref(),
+        });
+    }
+    Index { packages }
+}
+
+/// Writes a `checksums.txt` into each package directory of the per-package layout and an
+/// index of all packages into the artifacts directory.
+pub fn write_package_index(artifacts_dir: &Path, artifacts: &[Artifact]) {
+    let index = index(artifacts);
+    for (package, package_index) in &index.packages {
+        let prefix = format!("{}/", package);
+        let files = artifacts
+            .iter()
+            .filter(|artifact| artifact.package == *package)
+            .flat_map(Artifact::checksums)
+            .map(|(file, sha256)| match file.strip_prefix(&prefix) {
+                Some(file) => (file.to_string(), sha256),
+                None => (file, sha256),
+            })
+            .collect::<Vec<_>>();
+        let checksums =
+            format_checksums(files.iter().map(|(file, sha256)| (file.as_str(), *sha256)));
+        fs::write(artifacts_dir.join(&package_index.checksums), checksums)
+            .expect("Failed to write package checksums");
+    }
+    let json = serde_json::to_string_pretty(&index).unwrap();
+    fs::write(artifacts_dir.join(INDEX_FILE), json + "\n").expect("Failed to write index");
+}
+
+/// Returns an artifact of the *build* of *package*, named like in the flat layout and with
+/// everything else empty, for tests to fill in what they need.
+#[cfg(test)]
+pub(crate) fn test_artifact(package: &str, build: &str) -> Artifact {
+    let file = if build.is_empty() {
+        format!("{}.wasm", package)
+    } else {
+        format!("{}-{}.wasm", package, build)
+    };
+    Artifact {
+        file,
+        package: package.to_string(),
+        build: build.to_string(),
+        features: BTreeSet::new(),
+        default_features: true,
+        cosmwasm_target: CosmWasmTarget::V3,
+        required_capabilities: BTreeSet::new(),
+        entry_points: BTreeSet::new(),
+        absolute_paths: BTreeSet::new(),
+        sha256: String::new(),
+        size: 0,
+        gzip_sha256: None,
+        intermediate_sha256: String::new(),
+        intermediate_size: 0,
+        intermediate_path: None,
+        cargo_args: vec![],
+        rustflags: vec![],
+        rustc_version: String::new(),
+        wasm_opt_flags: vec![],
+        schema: BTreeMap::new(),
+        cache_hit: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn artifact(file: &str, package: &str, build: &str) -> Artifact {
+        Artifact {
+            file: file.to_string(),
+            sha256: format!("{}-sha256", file),
+            ..test_artifact(package, build)
+        }
+    }
+
+    #[test]
+    fn metadata_file_works() {
+        assert_eq!(
+            artifact("my_contract-debug.wasm", "my_contract", "debug").metadata_file(),
+            "my_contract-debug.metadata.json"
+        );
+        assert_eq!(
+            artifact("my_contract/debug.wasm", "my_contract", "debug").metadata_file(),
+            "my_contract/debug.metadata.json"
+        );
+    }
+
+    #[test]
+    fn checksums_works() {
+        let mut artifact = artifact("my_contract.wasm", "my_contract", "");
+        assert_eq!(
+            artifact.checksums(),
+            vec![("my_contract.wasm".to_string(), "my_contract.wasm-sha256")]
+        );
+
+        artifact.gzip_sha256 = Some("gzip-sha256".to_string());
+        assert_eq!(
+            artifact.checksums(),
+            vec![
+                ("my_contract.wasm".to_string(), "my_contract.wasm-sha256"),
+                ("my_contract.wasm.gz".to_string(), "gzip-sha256"),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_checksums_works() {
+        let artifacts_dir = env::temp_dir().join(format!("bob-checksums-{}", std::process::id()));
+        fs::create_dir_all(&artifacts_dir).unwrap();
+        let mut compiled = artifact("my_contract/debug.wasm", "my_contract", "debug");
+        compiled.intermediate_sha256 = "cargo-output-sha256".to_string();
+        compiled.intermediate_path = Some(PathBuf::from(
+            "/target/determinism/wasm32-unknown-unknown/release/my_contract-debug.wasm",
+        ));
+        let cached = artifact("my_contract.wasm", "my_contract", "");
+        let artifacts = [compiled, cached];
+
+        write_checksums(&artifacts_dir, &artifacts, true);
+        assert_eq!(
+            fs::read_to_string(artifacts_dir.join(CHECKSUMS_FILE)).unwrap(),
+            "my_contract.wasm-sha256  my_contract.wasm\n\
+             my_contract/debug.wasm-sha256  my_contract/debug.wasm\n"
+        );
+        assert_eq!(
+            fs::read_to_string(artifacts_dir.join(INTERMEDIATE_CHECKSUMS_FILE)).unwrap(),
+            "cargo-output-sha256  /target/determinism/wasm32-unknown-unknown/release/my_contract-debug.wasm\n"
+        );
+
+        write_checksums(&artifacts_dir, &artifacts, false);
+        assert!(!artifacts_dir.join(INTERMEDIATE_CHECKSUMS_FILE).exists());
+
+        fs::remove_dir_all(&artifacts_dir).unwrap();
+    }
+
+    #[test]
+    fn index_works() {
+        let artifacts = [
+            artifact("one/default.wasm", "one", ""),
+            artifact("one/debug.wasm", "one", "debug"),
+            artifact("two/default.wasm", "two", ""),
+        ];
+        assert_eq!(
+            serde_json::to_value(index(&artifacts)).unwrap(),
+            serde_json::json!({
+                "packages": {
+                    "one": {
+                        "checksums": "one/checksums.txt",
+                        "artifacts": [
+                            { "build": "", "file": "one/default.wasm", "sha256": "one/default.wasm-sha256" },
+                            { "build": "debug", "file": "one/debug.wasm", "sha256": "one/debug.wasm-sha256" }
+                        ]
+                    },
+                    "two": {
+                        "checksums": "two/checksums.txt",
+                        "artifacts": [
+                            { "build": "", "file": "two/default.wasm", "sha256": "two/default.wasm-sha256" }
+                        ]
+                    }
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    cargo_command,
+    checksum::sha256,
+    events::Event,
+    pkg_build::{forward_tail, BuildError, OUTPUT_TAIL_LINES},
+    toolchain::Toolchain,
+    Options,
+};
+
+/// The target directory for the schema binaries, which are built for the host
+const SCHEMA_TARGET_DIR: &str = "/target/schema";
+
+/// Source of the binary that writes the JSON schema via `write_api!`
+const SCHEMA_BINARY: &str = "src/bin/schema.rs";
+
+/// Returns the directory the schema of *package_name* is written to.
+pub fn schema_dir(artifacts_dir: &Path, package_name: &str) -> PathBuf {
+    artifacts_dir.join(package_name).join("schema")
+}
+
+/// Builds and runs the schema binary of the package in *dir*, which writes the schema files
+/// to `<artifacts_dir>/<package_name>/schema/`.
+///
+/// Returns the SHA-256 hashes of the schema files by their path relative to the artifacts
+/// directory, or `None` if the package has no schema binary. Fails with the end of the output
+/// if building or running the binary does not succeed.
+pub fn generate(
+    dir: &Path,
+    package_name: &str,
+    options: &Options,
+    toolchain: &Toolchain,
+) -> Result<Option<BTreeMap<String, String>>, BuildError> {
+    if !dir.join(SCHEMA_BINARY).is_file() {
+        options.emit(Event::Note {
+            id: None,
+            message: format!(
+                "{} has no {}, skipping schema generation",
+                package_name, SCHEMA_BINARY
+            ),
+        });
+        return Ok(None);
+    }
+
+    options.emit(Event::SchemaStarted {
+        package: package_name.to_string(),
+    });
+    let mut args = vec![
+        "build".to_string(),
+        "--locked".to_string(),
+        "--bin=schema".to_string(),
+        format!("--target-dir={}", SCHEMA_TARGET_DIR),
+    ];
+    if options.offline {
+        args.push("--offline".to_string());
+    }
+    let mut child = cargo_command(options, toolchain)
+        .args(&args)
+        .current_dir(dir)
+        // Keep stdout to bob's own messages
+        .stdout(Stdio::from(io::stderr()))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let output = forward_tail(child.stderr.take().unwrap(), OUTPUT_TAIL_LINES);
+    let status = child.wait().unwrap();
+    if !status.success() {
+        return Err(BuildError {
+            message: format!(
+                "Building the schema binary of {} failed ({})",
+                package_name, status
+            ),
+            output,
+        });
+    }
+
+    // `write_api!` writes to `schema/` in the working directory
+    let package_dir = options.artifacts_dir.join(package_name);
+    let schema_dir = schema_dir(&options.artifacts_dir, package_name);
+    if schema_dir.exists() {
+        fs::remove_dir_all(&schema_dir).unwrap();
+    }
+    fs::create_dir_all(&package_dir).unwrap();
+    let mut child = Command::new(Path::new(SCHEMA_TARGET_DIR).join("debug/schema"))
+        .current_dir(&package_dir)
+        // Keep stdout to bob's own messages
+        .stdout(Stdio::from(io::stderr()))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let output = forward_tail(child.stderr.take().unwrap(), OUTPUT_TAIL_LINES);
+    let status = child.wait().unwrap();
+    if !status.success() {
+        return Err(BuildError {
+            message: format!("Schema generation of {} failed ({})", package_name, status),
+            output,
+        });
+    }
+
+    Ok(Some(hash_files(&options.artifacts_dir, &schema_dir)))
+}
+
+/// Hashes all files in *dir* by their path relative to *artifacts_dir*.
+fn hash_files(artifacts_dir: &Path, dir: &Path) -> BTreeMap<String, String> {
+    let mut hashes = BTreeMap::new();
+    let mut queue = vec![dir.to_path_buf()];
+    while let Some(current) = queue.pop() {
+        for entry in fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                queue.push(path);
+            } else {
+                let relative = path.strip_prefix(artifacts_dir).unwrap();
+                hashes.insert(
+                    relative.to_string_lossy().to_string(),
+                    sha256(&fs::read(&path).unwrap()),
+                );
+            }
+        }
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn hash_files_works() {
+        let artifacts_dir = env::temp_dir().join(format!("bob-schema-{}", std::process::id()));
+        let dir = schema_dir(&artifacts_dir, "my_contract");
+        fs::create_dir_all(dir.join("raw")).unwrap();
+        fs::write(dir.join("my_contract.json"), "{}").unwrap();
+        fs::write(dir.join("raw/execute.json"), "").unwrap();
+
+        assert_eq!(
+            hash_files(&artifacts_dir, &dir),
+            BTreeMap::from([
+                (
+                    "my_contract/schema/my_contract.json".to_string(),
+                    sha256(b"{}")
+                ),
+                (
+                    "my_contract/schema/raw/execute.json".to_string(),
+                    sha256(b"")
+                ),
+            ])
+        );
+
+        fs::remove_dir_all(&artifacts_dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{naming::STANDARD_BUILD_NAME, report::Artifact};
+
+/// Number of hex digits of the SHA-256 hash shown in the summary
+const SHA256_PREFIX_LEN: usize = 12;
+
+/// What we need to know about an artifact of a previous manifest to compare sizes
+#[derive(Deserialize, Debug)]
+struct PreviousArtifact {
+    package: String,
+    #[serde(default)]
+    build: String,
+    /// Missing in manifests of older versions
+    size: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PreviousManifest {
+    artifacts: Vec<PreviousArtifact>,
+}
+
+/// Sizes of the artifacts of a previous run by package and build name
+pub type PreviousSizes = BTreeMap<(String, String), u64>;
+
+/// Reads the artifact sizes from the `manifest.json` of a previous run.
+pub fn read_previous_sizes(manifest: &Path) -> PreviousSizes {
+    let content = fs::read_to_string(manifest).expect("Failed to read previous manifest");
+    let manifest: PreviousManifest =
+        serde_json::from_str(&content).expect("Failed to parse previous manifest");
+    manifest
+        .artifacts
+        .into_iter()
+        .filter_map(|artifact| Some(((artifact.package, artifact.build), artifact.size?)))
+        .collect()
+}
+
+fn kib(size: u64) -> String {
+    format!("{:.1} KiB", size as f64 / 1024.0)
+}
+
+/// Formats the change from *previous* to *current* bytes, e.g. `+512 B (+1.2%)`. There is no
+/// percentage if the previous artifact was empty.
+fn delta(previous: u64, current: u64) -> String {
+    let difference = current as i64 - previous as i64;
+    if difference == 0 {
+        return "±0 B".to_string();
+    }
+    if previous == 0 {
+        return format!("{:+} B", difference);
+    }
+    let percent = difference as f64 * 100.0 / previous as f64;
+    format!("{:+} B ({:+.1}%)", difference, percent)
+}
+
+/// Renders a Markdown table with one row per artifact. With the sizes of a *previous* run,
+/// a column shows how the size of each artifact changed.
+pub fn render(artifacts: &[Artifact], previous: Option<&PreviousSizes>) -> String {
+    let mut markdown = String::from("## Build Summary\n\n");
+    markdown +=
+        "| Package | Build | Features | Default features | Cargo output | Optimized | SHA-256 |";
+    markdown += if previous.is_some() {
+        " Change |\n"
+    } else {
+        "\n"
+    };
+    markdown += "| --- | --- | --- | --- | ---: | ---: | --- |";
+    markdown += if previous.is_some() {
+        " ---: |\n"
+    } else {
+        "\n"
+    };
+
+    for artifact in artifacts {
+        let build = if artifact.build.is_empty() {
+            STANDARD_BUILD_NAME
+        } else {
+            &artifact.build
+        };
+        let features = if artifact.features.is_empty() {
+            "-".to_string()
+        } else {
+            artifact
+                .features
+                .iter()
+                .map(|feature| format!("`{}`", feature))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        markdown += &format!(
+            "| {} | {} | {} | {} | {} | {} | `{}` |",
+            artifact.package,
+            build,
+            features,
+            artifact.default_features,
+            kib(artifact.intermediate_size),
+            kib(artifact.size),
+            &artifact.sha256[..SHA256_PREFIX_LEN.min(artifact.sha256.len())]
+        );
+        if let Some(previous) = previous {
+            let key = (artifact.package.clone(), artifact.build.clone());
+            let change = match previous.get(&key) {
+                Some(size) => delta(*size, artifact.size),
+                None => "new".to_string(),
+            };
+            markdown += &format!(" {} |", change);
+        }
+        markdown += "\n";
+    }
+    markdown
+}
+
+/// Writes the Markdown summary of the *artifacts* to *path*.
+pub fn write_summary(path: &Path, artifacts: &[Artifact], previous: Option<&PreviousSizes>) {
+    fs::write(path, render(artifacts, previous)).expect("Failed to write summary");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::report::test_artifact;
+
+    fn artifact(build: &str, features: &[&str], size: u64) -> Artifact {
+        Artifact {
+            file: String::new(),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            default_features: build.is_empty(),
+            sha256: "0123456789abcdef0123456789abcdef".to_string(),
+            size,
+            intermediate_size: 2 * size,
+            ..test_artifact("my_contract", build)
+        }
+    }
+
+    #[test]
+    fn render_works() {
+        let artifacts = [
+            artifact("", &[], 204800),
+            artifact("debug", &["debug", "iterator"], 1024),
+        ];
+        assert_eq!(
+            render(&artifacts, None),
+            "## Build Summary\n\n\
+             | Package | Build | Features | Default features | Cargo output | Optimized | SHA-256 |\n\
+             | --- | --- | --- | --- | ---: | ---: | --- |\n\
+             | my_contract | default | - | true | 400.0 KiB | 200.0 KiB | `0123456789ab` |\n\
+             | my_contract | debug | `debug`, `iterator` | false | 2.0 KiB | 1.0 KiB | `0123456789ab` |\n"
+        );
+
+        let previous = PreviousSizes::from([(("my_contract".to_string(), String::new()), 200000)]);
+        let rendered = render(&artifacts, Some(&previous));
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert!(lines[2].ends_with(" Change |"));
+        assert!(lines[4].ends_with(" +4800 B (+2.4%) |"));
+        assert!(lines[5].ends_with(" new |"));
+    }
+
+    #[test]
+    fn delta_works() {
+        assert_eq!(delta(1000, 1000), "±0 B");
+        assert_eq!(delta(1000, 900), "-100 B (-10.0%)");
+        assert_eq!(delta(1000, 1005), "+5 B (+0.5%)");
+        assert_eq!(delta(0, 0), "±0 B");
+        assert_eq!(delta(0, 1024), "+1024 B");
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// A step of the pipeline whose duration is measured
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Reading the `Cargo.toml` files of the project
+    ParseManifests,
+    /// Hashing the inputs of a package for the build cache
+    CacheInputs,
+    /// `cargo build` of one build, twice with the determinism check
+    Compile,
+    /// `wasm-opt`, validation and analysis of one artifact
+    Optimize,
+    /// Writing the `.wasm.gz` of one artifact
+    Compress,
+    /// Building and running the schema binary of a package
+    Schema,
+    /// Writing the checksum files
+    Checksums,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::ParseManifests => "parse_manifests",
+            Phase::CacheInputs => "cache_inputs",
+            Phase::Compile => "compile",
+            Phase::Optimize => "optimize",
+            Phase::Compress => "compress",
+            Phase::Schema => "schema",
+            Phase::Checksums => "checksums",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How long a phase took for a package and build
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Timing {
+    /// `None` for phases concerning the whole project
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// `None` for phases concerning the whole package. Empty for the standard build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    pub phase: Phase,
+    pub seconds: f64,
+}
+
+/// The durations of all phases of a run
+#[derive(Debug)]
+pub struct Timings {
+    /// When the run started
+    start: Instant,
+    entries: Vec<Timing>,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings {
+            start: Instant::now(),
+            entries: vec![],
+        }
+    }
+}
+
+/// Durations summed up per phase and per package
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Totals {
+    /// Wall time of the whole run, including the steps that are not measured as a phase
+    pub seconds: f64,
+    pub phases: BTreeMap<Phase, f64>,
+    pub packages: BTreeMap<String, f64>,
+}
+
+/// What goes into the manifest
+#[derive(Serialize, Debug)]
+pub struct TimingReport<'a> {
+    pub totals: Totals,
+    pub entries: &'a [Timing],
+}
+
+/// Rounds to milliseconds, which is precise enough and keeps the reports readable.
+fn round(seconds: f64) -> f64 {
+    (seconds * 1000.0).round() / 1000.0
+}
+
+impl Timings {
+    /// Runs *f* and records its duration as *phase* of *package* and *build*.
+    pub fn measure<T>(
+        &mut self,
+        package: Option<&str>,
+        build: Option<&str>,
+        phase: Phase,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(package, build, phase, start.elapsed());
+        result
+    }
+
+    /// Records that *phase* of *package* and *build* took *duration*.
+    pub fn record(
+        &mut self,
+        package: Option<&str>,
+        build: Option<&str>,
+        phase: Phase,
+        duration: Duration,
+    ) {
+        self.entries.push(Timing {
+            package: package.map(str::to_string),
+            build: build.map(str::to_string),
+            phase,
+            seconds: round(duration.as_secs_f64()),
+        });
+    }
+
+    /// Sums up the durations per phase and per package. The total is the time since the
+    /// timings were created.
+    pub fn totals(&self) -> Totals {
+        let mut totals = Totals {
+            seconds: round(self.start.elapsed().as_secs_f64()),
+            phases: BTreeMap::new(),
+            packages: BTreeMap::new(),
+        };
+        for timing in &self.entries {
+            *totals.phases.entry(timing.phase).or_default() += timing.seconds;
+            if let Some(package) = &timing.package {
+                *totals.packages.entry(package.clone()).or_default() += timing.seconds;
+            }
+        }
+        totals
+            .phases
+            .values_mut()
+            .for_each(|seconds| *seconds = round(*seconds));
+        totals
+            .packages
+            .values_mut()
+            .for_each(|seconds| *seconds = round(*seconds));
+        totals
+    }
+
+    /// Returns the totals together with all measurements.
+    pub fn report(&self) -> TimingReport<'_> {
+        TimingReport {
+            totals: self.totals(),
+            entries: &self.entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_works() {
+        let mut timings = Timings {
+            start: Instant::now() - Duration::from_secs(60),
+            entries: vec![],
+        };
+        timings.record(None, None, Phase::ParseManifests, Duration::from_millis(10));
+        timings.record(Some("a"), Some(""), Phase::Compile, Duration::from_secs(2));
+        timings.record(
+            Some("a"),
+            Some("debug"),
+            Phase::Compile,
+            Duration::from_secs(3),
+        );
+        timings.record(
+            Some("a"),
+            Some(""),
+            Phase::Optimize,
+            Duration::from_millis(500),
+        );
+        timings.record(Some("b"), None, Phase::Schema, Duration::from_secs(1));
+
+        let totals = timings.totals();
+        // The wall time, not the sum of the phases
+        assert!(totals.seconds >= 60.0 && totals.seconds < 120.0);
+        assert_eq!(
+            totals.phases,
+            BTreeMap::from([
+                (Phase::ParseManifests, 0.01),
+                (Phase::Compile, 5.0),
+                (Phase::Optimize, 0.5),
+                (Phase::Schema, 1.0),
+            ])
+        );
+        assert_eq!(
+            totals.packages,
+            BTreeMap::from([("a".to_string(), 5.5), ("b".to_string(), 1.0)])
+        );
+
+        let result = timings.measure(Some("b"), Some(""), Phase::Compress, || 42);
+        assert_eq!(result, 42);
+        assert_eq!(
+            serde_json::to_string(&timings.entries[5]).unwrap(),
+            r#"{"package":"b","build":"","phase":"compress","seconds":0.0}"#
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::RUSTC_PATH;
+
+const RUSTUP_PATH: &str = "rustup";
+
+/// Toolchain files rustup looks for, in order of precedence
+const TOOLCHAIN_FILES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+/// Channels that stand for a different Rust version over time
+const RELEASE_CHANNELS: &[&str] = &["stable", "beta", "nightly"];
+
+/// What to do if a toolchain file requests a different toolchain than the active one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolchainPolicy {
+    /// Print a warning and build with the active toolchain
+    #[default]
+    Warn,
+    /// Abort before compiling anything
+    Fail,
+    /// Build with the requested toolchain if it is installed, fail otherwise
+    Use,
+}
+
+impl FromStr for ToolchainPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(ToolchainPolicy::Warn),
+            "fail" => Ok(ToolchainPolicy::Fail),
+            "use" => Ok(ToolchainPolicy::Use),
+            _ => Err(format!(
+                "Unknown toolchain policy {:?}, expected warn, fail or use",
+                s
+            )),
+        }
+    }
+}
+
+/// A Rust toolchain installed via rustup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    /// Name passed to rustup via `RUSTUP_TOOLCHAIN`, e.g. `1.86.0-x86_64-unknown-linux-musl`
+    pub name: String,
+    /// Output of `rustc --version`
+    pub rustc_version: String,
+}
+
+impl fmt::Display for Toolchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.rustc_version)
+    }
+}
+
+impl Toolchain {
+    /// Returns the toolchain that is active outside of the project, i.e. `RUSTUP_TOOLCHAIN`
+    /// or the rustup default.
+    pub fn active() -> Self {
+        let name = env::var("RUSTUP_TOOLCHAIN").unwrap_or_else(|_| {
+            let default = rustup(&["default"]);
+            default
+                .split_whitespace()
+                .next()
+                .expect("No default toolchain")
+                .to_string()
+        });
+        Self::named(name)
+    }
+
+    fn named(name: String) -> Self {
+        let output = Command::new(RUSTC_PATH)
+            .arg("--version")
+            .env("RUSTUP_TOOLCHAIN", &name)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "Failed to run rustc of {}", name);
+        let rustc_version = String::from_utf8(output.stdout).unwrap().trim().to_string();
+        Toolchain {
+            name,
+            rustc_version,
+        }
+    }
+
+    /// Checks if this toolchain is the one requested by *channel*, e.g. `1.86.0`, `1.86`
+    /// or `nightly-2024-05-01`. Release channels like `stable` only match toolchains named
+    /// after them; `resolve` compares the version of the installed toolchain for them.
+    pub fn matches(&self, channel: &str) -> bool {
+        let version = self.rustc_version.split_whitespace().nth(1).unwrap_or("");
+        is_toolchain_name(&self.name, channel)
+            || version == channel
+            || version.starts_with(&format!("{}.", channel))
+    }
+}
+
+fn is_toolchain_name(name: &str, channel: &str) -> bool {
+    name == channel || name.starts_with(&format!("{}-", channel))
+}
+
+fn rustup(args: &[&str]) -> String {
+    let output = Command::new(RUSTUP_PATH)
+        .args(args)
+        .output()
+        .expect("Failed to run rustup");
+    assert!(output.status.success(), "rustup {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[derive(Deserialize)]
+struct ToolchainFile {
+    toolchain: ToolchainSection,
+}
+
+#[derive(Deserialize)]
+struct ToolchainSection {
+    channel: Option<String>,
+}
+
+/// Returns the channel of a `rust-toolchain.toml` or of a legacy `rust-toolchain` file
+/// that contains nothing but the channel.
+fn parse_channel(content: &str) -> Result<Option<String>, String> {
+    let trimmed = content.trim();
+    if !trimmed.is_empty() && !trimmed.contains(['[', '=', '\n']) {
+        return Ok(Some(trimmed.to_string()));
+    }
+    let file: ToolchainFile = toml::from_str(content).map_err(|err| err.to_string())?;
+    Ok(file.toolchain.channel)
+}
+
+/// Finds the toolchain file rustup would use for a package in *dir*, which is the
+/// first one found going up through the parent directories to *root*.
+fn find_toolchain_file(root: &Path, dir: &Path) -> Option<PathBuf> {
+    // The ancestors of a relative *dir* end with the empty path, which stands for
+    // the current directory
+    let mut dirs = dir
+        .ancestors()
+        .take_while(|ancestor| *ancestor != root && !ancestor.as_os_str().is_empty())
+        .collect::<Vec<_>>();
+    dirs.push(root);
+    dirs.into_iter()
+        .flat_map(|dir| TOOLCHAIN_FILES.iter().map(move |file| dir.join(file)))
+        .find(|path| path.is_file())
+}
+
+/// Determines the toolchain to build the package in *dir* with according to the toolchain
+/// files in *dir*, its parents and *root*, and the *policy*.
+///
+/// Returns the toolchain together with a warning if the toolchain file is ignored, or an error
+/// if the package cannot be built under the policy.
+pub fn resolve(
+    root: &Path,
+    dir: &Path,
+    active: &Toolchain,
+    policy: ToolchainPolicy,
+) -> Result<(Toolchain, Option<String>), String> {
+    let Some(path) = find_toolchain_file(root, dir) else {
+        return Ok((active.clone(), None));
+    };
+    let content = fs::read_to_string(&path).unwrap();
+    let channel = match parse_channel(&content) {
+        Ok(Some(channel)) => channel,
+        Ok(None) => return Ok((active.clone(), None)),
+        Err(err) => return Err(format!("Failed to parse {}: {}", path.display(), err)),
+    };
+    if active.matches(&channel) {
+        return Ok((active.clone(), None));
+    }
+    // E.g. `stable` matches an active `1.86.0` if the installed stable toolchain is 1.86.0
+    let requested = RELEASE_CHANNELS
+        .contains(&channel.as_str())
+        .then(|| installed_toolchain(&channel))
+        .flatten();
+    if let Some(requested) = &requested {
+        if requested.rustc_version == active.rustc_version {
+            return Ok((active.clone(), None));
+        }
+    }
+
+    let mismatch = format!(
+        "{} requests toolchain {:?} but the active toolchain is {}",
+        path.display(),
+        channel,
+        active
+    );
+    match policy {
+        ToolchainPolicy::Warn => {
+            let warning = format!("{}. Building with {}.", mismatch, active.name);
+            Ok((active.clone(), Some(warning)))
+        }
+        ToolchainPolicy::Fail => Err(mismatch),
+        ToolchainPolicy::Use => match requested.or_else(|| installed_toolchain(&channel)) {
+            Some(toolchain) => Ok((toolchain, None)),
+            None => Err(format!("{} and {:?} is not installed", mismatch, channel)),
+        },
+    }
+}
+
+/// Returns the installed toolchain for *channel*, if any.
+fn installed_toolchain(channel: &str) -> Option<Toolchain> {
+    let installed = rustup(&["toolchain", "list"]);
+    find_installed(&installed, channel).map(Toolchain::named)
+}
+
+/// Finds the toolchain for *channel* in the output of `rustup toolchain list`.
+fn find_installed(toolchain_list: &str, channel: &str) -> Option<String> {
+    toolchain_list
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .find(|name| is_toolchain_name(name, channel))
+        .map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_channel_works() {
+        assert_eq!(
+            parse_channel(
+                "[toolchain]\nchannel = \"1.81.0\"\ntargets = [\"wasm32-unknown-unknown\"]\n"
+            )
+            .unwrap(),
+            Some("1.81.0".to_string())
+        );
+        assert_eq!(
+            parse_channel("nightly-2024-05-01\n").unwrap(),
+            Some("nightly-2024-05-01".to_string())
+        );
+        assert_eq!(
+            parse_channel("[toolchain]\npath = \"/opt/rust\"\n").unwrap(),
+            None
+        );
+        parse_channel("[toolchain\n").unwrap_err();
+    }
+
+    #[test]
+    fn matches_works() {
+        let toolchain = Toolchain {
+            name: "1.86.0-x86_64-unknown-linux-musl".to_string(),
+            rustc_version: "rustc 1.86.0 (05f9846f8 2025-03-31)".to_string(),
+        };
+        assert!(toolchain.matches("1.86.0"));
+        assert!(toolchain.matches("1.86"));
+        assert!(!toolchain.matches("1.8"));
+        assert!(!toolchain.matches("1.81.0"));
+        // Resolved via rustup instead
+        assert!(!toolchain.matches("stable"));
+
+        let stable = Toolchain {
+            name: "stable-x86_64-unknown-linux-gnu".to_string(),
+            rustc_version: "rustc 1.86.0 (05f9846f8 2025-03-31)".to_string(),
+        };
+        assert!(stable.matches("stable"));
+        assert!(!stable.matches("nightly"));
+    }
+
+    #[test]
+    fn find_toolchain_file_works() {
+        let root = env::temp_dir().join(format!("bob-toolchain-{}", std::process::id()));
+        let dir = root.join("contracts/group/my_contract");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(find_toolchain_file(&root, &dir), None);
+
+        fs::write(root.join("rust-toolchain.toml"), "").unwrap();
+        assert_eq!(
+            find_toolchain_file(&root, &dir),
+            Some(root.join("rust-toolchain.toml"))
+        );
+        // Directories between the package and the root count as well
+        fs::write(root.join("contracts/rust-toolchain"), "").unwrap();
+        assert_eq!(
+            find_toolchain_file(&root, &dir),
+            Some(root.join("contracts/rust-toolchain"))
+        );
+        fs::write(dir.join("rust-toolchain.toml"), "").unwrap();
+        assert_eq!(
+            find_toolchain_file(&root, &dir),
+            Some(dir.join("rust-toolchain.toml"))
+        );
+        assert_eq!(
+            find_toolchain_file(&root, &root),
+            Some(root.join("rust-toolchain.toml"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn find_installed_works() {
+        let list = "stable-x86_64-unknown-linux-gnu (default)\n\
+            1.81.0-x86_64-unknown-linux-gnu\n\
+            nightly-2024-05-01-x86_64-unknown-linux-gnu\n";
+        assert_eq!(
+            find_installed(list, "1.81.0"),
+            Some("1.81.0-x86_64-unknown-linux-gnu".to_string())
+        );
+        assert_eq!(
+            find_installed(list, "stable"),
+            Some("stable-x86_64-unknown-linux-gnu".to_string())
+        );
+        assert_eq!(find_installed(list, "1.81"), None);
+        assert_eq!(find_installed(list, "beta"), None);
+    }
+}
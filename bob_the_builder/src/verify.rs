@@ -0,0 +1,289 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compress::GZIP_SUFFIX,
+    events::{BuildId, Event},
+    report::{Artifact, MANIFEST_FILE},
+    Options,
+};
+
+/// The checksums of one artifact of the build we verify against.
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ReferenceArtifact {
+    pub file: String,
+    pub sha256: Option<String>,
+    /// Hash of the compressed artifact `<file>.gz`
+    pub gzip_sha256: Option<String>,
+    pub intermediate_sha256: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReferenceManifest {
+    artifacts: Vec<ReferenceArtifact>,
+}
+
+/// The result of comparing a rebuilt artifact with the reference.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Match,
+    /// Cargo produced different Wasm already
+    CargoOutputDiffers,
+    /// Cargo produced the same Wasm but `wasm-opt` did not
+    OptimizationDiffers,
+    /// The Wasm matches but its compressed form does not
+    GzipDiffers,
+    /// The reference has no intermediate checksum to tell where the difference comes from
+    Differs,
+    /// The rebuild produced an artifact the reference does not contain
+    NotInReference,
+}
+
+impl Outcome {
+    pub fn is_match(&self) -> bool {
+        *self == Outcome::Match
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Match => write!(f, "match"),
+            Outcome::CargoOutputDiffers => write!(f, "MISMATCH in cargo output"),
+            Outcome::OptimizationDiffers => {
+                write!(f, "MISMATCH after wasm-opt (cargo output matches)")
+            }
+            Outcome::GzipDiffers => write!(f, "MISMATCH in .gz (Wasm matches)"),
+            Outcome::Differs => write!(f, "MISMATCH (no intermediate checksum in reference)"),
+            Outcome::NotInReference => write!(f, "MISMATCH (not in reference)"),
+        }
+    }
+}
+
+/// Loads the reference checksums from a `manifest.json` or a `checksums.txt` file.
+/// For the latter, intermediate checksums are taken from a `manifest.json` in the same directory
+/// if there is one.
+pub fn load_reference(path: &Path) -> BTreeMap<String, ReferenceArtifact> {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read reference {}: {}", path.display(), err));
+    if path.extension().is_some_and(|ext| ext == "json") {
+        return parse_manifest(&content);
+    }
+
+    let mut reference = parse_checksums(&content);
+    let manifest_path = path.with_file_name(MANIFEST_FILE);
+    if let Ok(manifest) = fs::read_to_string(manifest_path) {
+        for (file, from_manifest) in parse_manifest(&manifest) {
+            if let Some(artifact) = reference.get_mut(&file) {
+                artifact.intermediate_sha256 = from_manifest.intermediate_sha256;
+            }
+        }
+    }
+    reference
+}
+
+/// Parses the `manifest.json` of a previous build.
+pub fn parse_manifest(content: &str) -> BTreeMap<String, ReferenceArtifact> {
+    let manifest: ReferenceManifest =
+        serde_json::from_str(content).expect("Failed to parse reference manifest");
+    manifest
+        .artifacts
+        .into_iter()
+        .map(|artifact| (artifact.file.clone(), artifact))
+        .collect()
+}
+
+/// Parses a `checksums.txt` in the output format of `sha256sum`.
+/// Checksums of `<file>.gz` are attached to the artifact `<file>`.
+pub fn parse_checksums(content: &str) -> BTreeMap<String, ReferenceArtifact> {
+    let mut reference = BTreeMap::<String, ReferenceArtifact>::new();
+    for (sha256, file) in content
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+    {
+        // `sha256sum` marks binary mode with a '*' in front of the file name
+        let file = file.trim_start().trim_start_matches('*');
+        let (file, compressed) = match file.strip_suffix(GZIP_SUFFIX) {
+            Some(file) => (file, true),
+            None => (file, false),
+        };
+        let artifact = reference
+            .entry(file.to_string())
+            .or_insert_with(|| ReferenceArtifact {
+                file: file.to_string(),
+                ..ReferenceArtifact::default()
+            });
+        if compressed {
+            artifact.gzip_sha256 = Some(sha256.to_string());
+        } else {
+            artifact.sha256 = Some(sha256.to_string());
+        }
+    }
+    reference
+}
+
+/// Compares a rebuilt artifact with its reference.
+pub fn compare(artifact: &Artifact, reference: Option<&ReferenceArtifact>) -> Outcome {
+    let Some(reference) = reference else {
+        return Outcome::NotInReference;
+    };
+    if reference.sha256.as_ref() == Some(&artifact.sha256) {
+        return match (&reference.gzip_sha256, &artifact.gzip_sha256) {
+            (Some(expected), Some(actual)) if expected != actual => Outcome::GzipDiffers,
+            _ => Outcome::Match,
+        };
+    }
+    match &reference.intermediate_sha256 {
+        None => Outcome::Differs,
+        Some(intermediate) if *intermediate == artifact.intermediate_sha256 => {
+            Outcome::OptimizationDiffers
+        }
+        Some(_) => Outcome::CargoOutputDiffers,
+    }
+}
+
+/// Reports the outcome of each rebuilt artifact and each reference artifact that was not
+/// rebuilt. Returns true if everything matches.
+pub fn report(
+    artifacts: &[Artifact],
+    reference: &BTreeMap<String, ReferenceArtifact>,
+    options: &Options,
+) -> bool {
+    let mut all_match = true;
+    for artifact in artifacts {
+        let outcome = compare(artifact, reference.get(&artifact.file));
+        options.emit(Event::ArtifactVerified {
+            id: BuildId {
+                package: artifact.package.clone(),
+                build: artifact.build.clone(),
+            },
+            file: artifact.file.clone(),
+            outcome,
+        });
+        all_match &= outcome.is_match();
+    }
+    for file in reference.keys() {
+        if !artifacts.iter().any(|artifact| &artifact.file == file) {
+            options.emit(Event::ReferenceNotRebuilt { file: file.clone() });
+            all_match = false;
+        }
+    }
+    all_match
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::report::test_artifact;
+
+    fn artifact(sha256: &str, intermediate_sha256: &str) -> Artifact {
+        Artifact {
+            sha256: sha256.to_string(),
+            intermediate_sha256: intermediate_sha256.to_string(),
+            ..test_artifact("my_contract", "")
+        }
+    }
+
+    #[test]
+    fn parse_checksums_works() {
+        let parsed = parse_checksums("aaaa  one.wasm\ncccc  one.wasm.gz\nbbbb *two-debug.wasm\n\n");
+        assert_eq!(
+            parsed,
+            BTreeMap::from([
+                (
+                    "one.wasm".to_string(),
+                    ReferenceArtifact {
+                        file: "one.wasm".to_string(),
+                        sha256: Some("aaaa".to_string()),
+                        gzip_sha256: Some("cccc".to_string()),
+                        intermediate_sha256: None,
+                    }
+                ),
+                (
+                    "two-debug.wasm".to_string(),
+                    ReferenceArtifact {
+                        file: "two-debug.wasm".to_string(),
+                        sha256: Some("bbbb".to_string()),
+                        gzip_sha256: None,
+                        intermediate_sha256: None,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_manifest_works() {
+        let parsed = parse_manifest(
+            r#"{
+                "artifacts": [
+                    { "file": "one.wasm", "package": "one", "sha256": "aaaa", "intermediate_sha256": "cccc" },
+                    { "file": "old.wasm", "package": "old" }
+                ]
+            }"#,
+        );
+        assert_eq!(
+            parsed["one.wasm"],
+            ReferenceArtifact {
+                file: "one.wasm".to_string(),
+                sha256: Some("aaaa".to_string()),
+                gzip_sha256: None,
+                intermediate_sha256: Some("cccc".to_string()),
+            }
+        );
+        assert_eq!(parsed["old.wasm"].sha256, None);
+    }
+
+    #[test]
+    fn compare_works() {
+        let reference = ReferenceArtifact {
+            file: "my_contract.wasm".to_string(),
+            sha256: Some("aaaa".to_string()),
+            gzip_sha256: None,
+            intermediate_sha256: Some("cccc".to_string()),
+        };
+        assert_eq!(
+            compare(&artifact("aaaa", "cccc"), Some(&reference)),
+            Outcome::Match
+        );
+        assert_eq!(
+            compare(&artifact("bbbb", "cccc"), Some(&reference)),
+            Outcome::OptimizationDiffers
+        );
+        assert_eq!(
+            compare(&artifact("bbbb", "dddd"), Some(&reference)),
+            Outcome::CargoOutputDiffers
+        );
+        assert_eq!(
+            compare(&artifact("aaaa", "cccc"), None),
+            Outcome::NotInReference
+        );
+
+        let mut compressed = artifact("aaaa", "cccc");
+        compressed.gzip_sha256 = Some("eeee".to_string());
+        let with_gzip = ReferenceArtifact {
+            file: "my_contract.wasm".to_string(),
+            sha256: Some("aaaa".to_string()),
+            gzip_sha256: Some("ffff".to_string()),
+            intermediate_sha256: None,
+        };
+        assert_eq!(compare(&compressed, Some(&with_gzip)), Outcome::GzipDiffers);
+        // Rebuilt without `--gzip`
+        assert_eq!(
+            compare(&artifact("aaaa", "cccc"), Some(&with_gzip)),
+            Outcome::Match
+        );
+
+        let without_intermediate = ReferenceArtifact {
+            intermediate_sha256: None,
+            ..reference
+        };
+        assert_eq!(
+            compare(&artifact("bbbb", "cccc"), Some(&without_intermediate)),
+            Outcome::Differs
+        );
+    }
+}
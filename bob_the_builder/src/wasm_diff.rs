@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, fmt};
+
+use wasmparser::{BinaryReaderError, ExternalKind, Parser, Payload, TypeRef};
+
+/// One structural difference between two Wasm modules.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// A section with different content, e.g. `data` or `custom "producers"`.
+    /// The sizes are `None` if the section is missing in one of the modules.
+    Section {
+        name: String,
+        left_size: Option<usize>,
+        right_size: Option<usize>,
+    },
+    /// A function with a different body.
+    /// The name is the export name of the function if it has one.
+    Function { index: u32, name: Option<String> },
+    /// The modules define a different number of functions.
+    FunctionCount { left: usize, right: usize },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Section {
+                name,
+                left_size,
+                right_size,
+            } => {
+                let size = |s: &Option<usize>| match s {
+                    Some(size) => format!("{} bytes", size),
+                    None => "missing".to_string(),
+                };
+                write!(
+                    f,
+                    "section {} differs ({} vs. {})",
+                    name,
+                    size(left_size),
+                    size(right_size)
+                )
+            }
+            Difference::Function { index, name: None } => {
+                write!(f, "function {} differs", index)
+            }
+            Difference::Function {
+                index,
+                name: Some(name),
+            } => write!(f, "function {} ({}) differs", index, name),
+            Difference::FunctionCount { left, right } => {
+                write!(f, "number of functions differs ({} vs. {})", left, right)
+            }
+        }
+    }
+}
+
+/// The parts of a module we compare
+#[derive(Default)]
+struct Structure<'a> {
+    /// Section contents by name. Repeated custom sections get a `#<n>` suffix.
+    sections: BTreeMap<String, &'a [u8]>,
+    /// Bodies of the functions defined in the module (i.e. not imported)
+    function_bodies: Vec<&'a [u8]>,
+    imported_functions: u32,
+    exported_functions: BTreeMap<u32, String>,
+}
+
+fn structure(wasm: &[u8]) -> Result<Structure<'_>, BinaryReaderError> {
+    let mut structure = Structure::default();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let payload = payload?;
+        match &payload {
+            Payload::ImportSection(reader) => {
+                for import in reader.clone() {
+                    if let TypeRef::Func(_) = import?.ty {
+                        structure.imported_functions += 1;
+                    }
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader.clone() {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        structure
+                            .exported_functions
+                            .insert(export.index, export.name.to_string());
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                structure.function_bodies.push(&wasm[body.range()]);
+            }
+            _ => {}
+        }
+
+        let Some((id, range)) = payload.as_section() else {
+            continue;
+        };
+        let name = match &payload {
+            Payload::CustomSection(reader) => format!("custom {:?}", reader.name()),
+            _ => section_name(id).to_string(),
+        };
+        let mut key = name.clone();
+        let mut n = 1;
+        while structure.sections.contains_key(&key) {
+            n += 1;
+            key = format!("{}#{}", name, n);
+        }
+        structure.sections.insert(key, &wasm[range]);
+    }
+    Ok(structure)
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "datacount",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+/// Lists the sections and functions that differ between the modules *left* and *right*.
+pub fn structural_diff(left: &[u8], right: &[u8]) -> Result<Vec<Difference>, BinaryReaderError> {
+    let left = structure(left)?;
+    let right = structure(right)?;
+
+    let mut differences = vec![];
+
+    let mut names = left.sections.keys().collect::<Vec<_>>();
+    names.extend(
+        right
+            .sections
+            .keys()
+            .filter(|name| !left.sections.contains_key(*name)),
+    );
+    for name in names {
+        let left_section = left.sections.get(name);
+        let right_section = right.sections.get(name);
+        if left_section != right_section {
+            differences.push(Difference::Section {
+                name: name.clone(),
+                left_size: left_section.map(|s| s.len()),
+                right_size: right_section.map(|s| s.len()),
+            });
+        }
+    }
+
+    if left.function_bodies.len() != right.function_bodies.len()
+        || left.imported_functions != right.imported_functions
+    {
+        differences.push(Difference::FunctionCount {
+            left: left.imported_functions as usize + left.function_bodies.len(),
+            right: right.imported_functions as usize + right.function_bodies.len(),
+        });
+    } else {
+        for (i, (l, r)) in left
+            .function_bodies
+            .iter()
+            .zip(right.function_bodies.iter())
+            .enumerate()
+        {
+            if l != r {
+                let index = left.imported_functions + i as u32;
+                differences.push(Difference::Function {
+                    index,
+                    name: left.exported_functions.get(&index).cloned(),
+                });
+            }
+        }
+    }
+
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structural_diff_works_for_equal_modules() {
+        let wasm = wat::parse_str(r#"(module (func (export "instantiate")))"#).unwrap();
+        assert_eq!(structural_diff(&wasm, &wasm).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn structural_diff_works() {
+        let left = wat::parse_str(
+            r#"
+            (module
+                (import "env" "db_read" (func (param i32) (result i32)))
+                (memory 1)
+                (func (export "instantiate") (result i32) i32.const 1)
+                (func (export "execute") (result i32) i32.const 2)
+                (data (i32.const 0) "abc")
+            )
+            "#,
+        )
+        .unwrap();
+        let right = wat::parse_str(
+            r#"
+            (module
+                (import "env" "db_read" (func (param i32) (result i32)))
+                (memory 1)
+                (func (export "instantiate") (result i32) i32.const 1)
+                (func (export "execute") (result i32) i32.const 3)
+                (data (i32.const 0) "abcd")
+            )
+            "#,
+        )
+        .unwrap();
+
+        let differences = structural_diff(&left, &right).unwrap();
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Section {
+                    name: "code".to_string(),
+                    left_size: Some(11),
+                    right_size: Some(11),
+                },
+                Difference::Section {
+                    name: "data".to_string(),
+                    left_size: Some(9),
+                    right_size: Some(10),
+                },
+                Difference::Function {
+                    index: 2,
+                    name: Some("execute".to_string()),
+                },
+            ]
+        );
+        assert_eq!(differences[2].to_string(), "function 2 (execute) differs");
+    }
+
+    #[test]
+    fn structural_diff_works_for_different_function_count() {
+        let left = wat::parse_str(r#"(module (func))"#).unwrap();
+        let right = wat::parse_str(r#"(module (func) (func))"#).unwrap();
+
+        let differences = structural_diff(&left, &right).unwrap();
+        assert!(differences.contains(&Difference::FunctionCount { left: 1, right: 2 }));
+    }
+}
@@ -13,10 +13,8 @@ cargo --version
 # Prepare artifacts directory for later use
 mkdir -p artifacts
 
-# Delete previously built artifacts. Those can exist if the image is called
-# with a cache mounted to /target. In cases where contracts are removed over time,
-# old builds in cache should not be contained in the result of the next build.
-rm -f /target/wasm32-unknown-unknown/release/*.wasm
+# Outputs of contracts and builds that no longer exist are removed by bob,
+# keeping the cargo outputs in /target of everything else.
 
 # There are two cases here
 # 1. The contract is included in the root workspace (eg. `cosmwasm-template`)
@@ -28,36 +26,19 @@ rm -f /target/wasm32-unknown-unknown/release/*.wasm
 # and build "/code/contracts/mycontract".
 # The default value for $1 is "." (see CMD in the Dockerfile).
 
-# Ensure we get exactly one argument and this is a directory (the path to the Cargo project to be built)
-if [ "$#" -ne 1 ] || ! [ -d "$1" ]; then
-  echo "Usage: $0 DIRECTORY" >&2
+# Ensure the first argument is a directory (the path to the Cargo project to be built).
+# All further arguments are options passed on to bob.
+if [ "$#" -lt 1 ] || ! [ -d "$1" ]; then
+  echo "Usage: $0 DIRECTORY [BOB_OPTIONS...]" >&2
   exit 1
 fi
 PROJECTDIR="$1"
-echo "Building project $(realpath "$PROJECTDIR") ..."
+shift
+ARTIFACTSDIR="$(realpath artifacts)"
+echo "Building and optimizing project $(realpath "$PROJECTDIR") ..."
 (
   cd "$PROJECTDIR"
-  /usr/local/bin/bob
-)
-
-echo "Optimizing artifacts ..."
-for WASM in /target/wasm32-unknown-unknown/release/*.wasm; do
-  [ -e "$WASM" ] || continue # https://superuser.com/a/519493
-
-  OUT_FILENAME=$(basename "$WASM")
-  echo "Optimizing $OUT_FILENAME ..."
-  wasm-opt -Os "$WASM" -o "artifacts/$OUT_FILENAME"
-done
-
-echo "Post-processing artifacts..."
-(
-  cd artifacts
-
-  if test -n "$(find . -maxdepth 1 -name '*.wasm' -print -quit)"; then
-    sha256sum -- *.wasm | tee checksums.txt
-  else
-    echo "Warn: No .wasm file built. Check your build configuration in Cargo.toml."
-  fi
+  /usr/local/bin/bob build --artifacts-dir="$ARTIFACTSDIR" "$@"
 )
 
 echo "Done."